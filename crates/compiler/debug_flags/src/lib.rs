@@ -124,6 +124,12 @@ flags! {
     /// Type-checks the mono IR after specialization.
     ROC_CHECK_MONO_IR
 
+    /// Adds runtime checks to the generated refcounting helpers, crashing with a message when a
+    /// refcount pointer or a recursive union pointer is misaligned, or when a value is
+    /// decremented after it was freed. Also catches most reference cycles, which would otherwise
+    /// be freed in an endless loop. Cycles through the tail of a cons list are not always caught.
+    ROC_CHECK_REFCOUNT_HELPERS
+
     /// Writes a pretty-printed mono IR to stderr after function specialization.
     ROC_PRINT_IR_AFTER_SPECIALIZATION
 
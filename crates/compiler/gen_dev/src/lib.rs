@@ -37,6 +37,9 @@ pub enum AssemblyBackendMode {
     Binary,
     /// Provides a testing implementation of primitives (roc_alloc, roc_panic, etc)
     Test,
+    /// Like `Test`, but roc_alloc, roc_realloc and roc_dealloc are provided by the host,
+    /// so that refcount tests can see which allocations were freed
+    RefcountTest,
     /// Provides a testing implementation of primitives (roc_alloc, roc_panic, etc)
     Repl,
 }
@@ -46,6 +49,16 @@ impl AssemblyBackendMode {
         match self {
             AssemblyBackendMode::Binary => false,
             AssemblyBackendMode::Test => true,
+            AssemblyBackendMode::RefcountTest => false,
+            AssemblyBackendMode::Repl => true,
+        }
+    }
+
+    fn generate_os_wrappers(self) -> bool {
+        match self {
+            AssemblyBackendMode::Binary => false,
+            AssemblyBackendMode::Test => true,
+            AssemblyBackendMode::RefcountTest => true,
             AssemblyBackendMode::Repl => true,
         }
     }
@@ -54,6 +67,7 @@ impl AssemblyBackendMode {
         match self {
             AssemblyBackendMode::Binary => false,
             AssemblyBackendMode::Test => true,
+            AssemblyBackendMode::RefcountTest => true,
             AssemblyBackendMode::Repl => true,
         }
     }
@@ -62,6 +76,7 @@ impl AssemblyBackendMode {
         match self {
             AssemblyBackendMode::Binary => false,
             AssemblyBackendMode::Test => true,
+            AssemblyBackendMode::RefcountTest => true,
             AssemblyBackendMode::Repl => true,
         }
     }
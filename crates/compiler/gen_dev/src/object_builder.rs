@@ -477,7 +477,9 @@ fn build_object<'a, B: Backend<'a>>(
             "roc_dealloc".into(),
             "free".into(),
         );
+    }
 
+    if backend.env().mode.generate_os_wrappers() {
         // Extra symbols only required on unix systems.
         if matches!(output.format(), BinaryFormat::Elf | BinaryFormat::MachO) {
             generate_wrapper(
@@ -525,7 +527,7 @@ fn build_object<'a, B: Backend<'a>>(
             let ident_ids = interns.all_ident_ids.get_mut(&module_id).unwrap();
 
             match mode {
-                AssemblyBackendMode::Test => {
+                AssemblyBackendMode::Test | AssemblyBackendMode::RefcountTest => {
                     let test_helper = roc_mono::code_gen_help::test_helper(
                         code_gen_help,
                         ident_ids,
@@ -10,8 +10,8 @@ use crate::ir::{
     Proc, ProcLayout, SelfRecursive, Stmt, UpdateModeId,
 };
 use crate::layout::{
-    Builtin, InLayout, LambdaName, Layout, LayoutInterner, LayoutRepr, LayoutWrapper, Niche,
-    STLayoutInterner, UnionLayout,
+    round_up_to_alignment, Builtin, InLayout, LambdaName, Layout, LayoutInterner, LayoutRepr,
+    LayoutWrapper, Niche, STLayoutInterner, UnionLayout,
 };
 
 mod equality;
@@ -40,6 +40,7 @@ pub enum HelperOp {
     DecRef(JoinPointId),
     Reset,
     ResetRef,
+    IsUnique,
     Eq,
 }
 
@@ -69,6 +70,9 @@ struct Specialization<'a> {
 pub struct Context<'a> {
     new_linker_data: Vec<'a, (Symbol, ProcLayout<'a>)>,
     recursive_union: Option<UnionLayout<'a>>,
+    /// Literals used by the helper body currently being generated.
+    /// They are bound once at the top of the body and shared by every use.
+    hoisted_literals: Vec<'a, (Symbol, i128, InLayout<'a>)>,
     op: HelperOp,
 }
 
@@ -97,6 +101,8 @@ pub struct CodeGenHelp<'a> {
     layout_isize: InLayout<'a>,
     specializations: Vec<'a, Specialization<'a>>,
     debug_recursion_depth: usize,
+    /// Insert runtime sanity checks into the generated refcount helpers
+    check_refcounts: bool,
 }
 
 impl<'a> CodeGenHelp<'a> {
@@ -110,6 +116,7 @@ impl<'a> CodeGenHelp<'a> {
             layout_isize,
             specializations: Vec::with_capacity_in(16, arena),
             debug_recursion_depth: 0,
+            check_refcounts: roc_debug_flags::dbg_set!(roc_debug_flags::ROC_CHECK_REFCOUNT_HELPERS),
         }
     }
 
@@ -150,6 +157,7 @@ impl<'a> CodeGenHelp<'a> {
         let mut ctx = Context {
             new_linker_data: Vec::new_in(self.arena),
             recursive_union: None,
+            hoisted_literals: Vec::new_in(self.arena),
             op,
         };
 
@@ -202,6 +210,7 @@ impl<'a> CodeGenHelp<'a> {
         let mut ctx = Context {
             new_linker_data: Vec::new_in(self.arena),
             recursive_union: None,
+            hoisted_literals: Vec::new_in(self.arena),
             op: if resetref {
                 HelperOp::ResetRef
             } else {
@@ -240,6 +249,7 @@ impl<'a> CodeGenHelp<'a> {
         let mut ctx = Context {
             new_linker_data: Vec::new_in(self.arena),
             recursive_union: None,
+            hoisted_literals: Vec::new_in(self.arena),
             op,
         };
 
@@ -260,6 +270,7 @@ impl<'a> CodeGenHelp<'a> {
         let mut ctx = Context {
             new_linker_data: Vec::new_in(self.arena),
             recursive_union: None,
+            hoisted_literals: Vec::new_in(self.arena),
             op: HelperOp::Eq,
         };
 
@@ -310,6 +321,7 @@ impl<'a> CodeGenHelp<'a> {
                 match ctx.op {
                     Dec | DecRef(_) => (LAYOUT_UNIT, self.arena.alloc([arg])),
                     Reset | ResetRef => (layout, self.arena.alloc([layout])),
+                    IsUnique => (LAYOUT_BOOL, self.arena.alloc([layout])),
                     Inc => (LAYOUT_UNIT, self.arena.alloc([arg, self.layout_isize])),
                     IndirectDec => (LAYOUT_UNIT, arena.alloc([ptr_arg])),
                     IndirectInc => (LAYOUT_UNIT, arena.alloc([ptr_arg, self.layout_isize])),
@@ -373,6 +385,9 @@ impl<'a> CodeGenHelp<'a> {
             proc: None,
         });
 
+        // Literals are hoisted per helper, so set aside those of the helper we're nested in
+        let outer_literals = std::mem::replace(&mut ctx.hoisted_literals, Vec::new_in(self.arena));
+
         // Recursively generate the body of the Proc and sub-procs
         let (ret_layout, body) = match ctx.op {
             Inc | Dec | DecRef(_) => (
@@ -419,11 +434,24 @@ impl<'a> CodeGenHelp<'a> {
                     Symbol::ARG_1,
                 ),
             ),
+            IsUnique => (
+                LAYOUT_BOOL,
+                refcount::is_unique_proc_body(
+                    self,
+                    ident_ids,
+                    ctx,
+                    layout_interner,
+                    layout,
+                    Symbol::ARG_1,
+                ),
+            ),
             Eq => (
                 LAYOUT_BOOL,
                 equality::eq_generic(self, ident_ids, ctx, layout_interner, layout),
             ),
         };
+        let body = refcount::bind_hoisted_literals(self.arena, ctx, body);
+        ctx.hoisted_literals = outer_literals;
 
         let args: &'a [(InLayout<'a>, Symbol)] = {
             let roc_value = (layout, ARG_1);
@@ -432,7 +460,7 @@ impl<'a> CodeGenHelp<'a> {
                     let inc_amount = (self.layout_isize, ARG_2);
                     self.arena.alloc([roc_value, inc_amount])
                 }
-                Dec | DecRef(_) | Reset | ResetRef => self.arena.alloc([roc_value]),
+                Dec | DecRef(_) | Reset | ResetRef | IsUnique => self.arena.alloc([roc_value]),
                 IndirectInc => {
                     let ptr_layout =
                         layout_interner.insert_direct_no_semantic(LayoutRepr::Ptr(layout));
@@ -511,6 +539,11 @@ impl<'a> CodeGenHelp<'a> {
                 result: layout,
                 niche: Niche::NONE,
             },
+            HelperOp::IsUnique => ProcLayout {
+                arguments: self.arena.alloc([layout]),
+                result: LAYOUT_BOOL,
+                niche: Niche::NONE,
+            },
             HelperOp::DecRef(_) => unreachable!("No generated Proc for DecRef"),
             HelperOp::Eq => ProcLayout {
                 arguments: self.arena.alloc([layout, layout]),
@@ -596,43 +629,52 @@ impl<'a> CodeGenHelp<'a> {
 
     fn union_tail_recursion_fields(
         &self,
-        union_in_layout: InLayout<'a>,
+        layout_interner: &STLayoutInterner<'a>,
         union: UnionLayout<'a>,
     ) -> Option<Vec<'a, Option<usize>>> {
         use UnionLayout::*;
         match union {
             NonRecursive(_) => None,
 
-            Recursive(tags) => self.union_tail_recursion_fields_help(union_in_layout, tags),
+            Recursive(tags) => self.union_tail_recursion_fields_help(layout_interner, tags),
 
             NonNullableUnwrapped(field_layouts) => {
-                self.union_tail_recursion_fields_help(union_in_layout, &[field_layouts])
+                self.union_tail_recursion_fields_help(layout_interner, &[field_layouts])
             }
 
             NullableWrapped {
                 other_tags: tags, ..
-            } => self.union_tail_recursion_fields_help(union_in_layout, tags),
+            } => self.union_tail_recursion_fields_help(layout_interner, tags),
 
             NullableUnwrapped { other_fields, .. } => {
-                self.union_tail_recursion_fields_help(union_in_layout, &[other_fields])
+                self.union_tail_recursion_fields_help(layout_interner, &[other_fields])
             }
         }
     }
 
+    /// For each tag, the index of the first field that points directly back to the union.
+    /// Returns None if no tag has such a field, since then there is no spine to loop over.
     fn union_tail_recursion_fields_help(
         &self,
-        in_layout: InLayout<'a>,
+        layout_interner: &STLayoutInterner<'a>,
         tags: &[&'a [InLayout<'a>]],
     ) -> Option<Vec<'a, Option<usize>>> {
         let tailrec_indices = tags
             .iter()
-            .map(|fields| fields.iter().position(|f| *f == in_layout))
+            .map(|fields| {
+                fields.iter().position(|f| {
+                    matches!(
+                        layout_interner.get_repr(*f),
+                        LayoutRepr::RecursivePointer(_)
+                    )
+                })
+            })
             .collect_in::<Vec<_>>(self.arena);
 
         if tailrec_indices.iter().any(|i| i.is_some()) {
-            None
-        } else {
             Some(tailrec_indices)
+        } else {
+            None
         }
     }
 }
@@ -700,6 +742,7 @@ impl<'a> CallerProc<'a> {
         let mut ctx = Context {
             new_linker_data: Vec::new_in(arena),
             recursive_union: None,
+            hoisted_literals: Vec::new_in(arena),
             op: HelperOp::Eq,
         };
 
@@ -863,6 +906,7 @@ impl<'a> CallerProc<'a> {
         let mut ctx = Context {
             new_linker_data: Vec::new_in(arena),
             recursive_union: None,
+            hoisted_literals: Vec::new_in(arena),
             op: HelperOp::Eq,
         };
 
@@ -1035,6 +1079,57 @@ fn layout_needs_helper_proc<'a>(
     }
 }
 
+/// Byte offsets of the refcounted values inside a value of this layout, with the fields
+/// of nested structs listed individually. A runtime that drops values generically can walk
+/// these offsets instead of calling a specialized helper.
+/// Returns `None` if a refcounted value is inside a tag payload, since its offset then
+/// depends on the tag id.
+#[allow(dead_code)]
+pub(crate) fn refcounted_field_offsets<'a>(
+    arena: &'a Bump,
+    layout_interner: &STLayoutInterner<'a>,
+    layout: InLayout<'a>,
+) -> Option<Vec<'a, u32>> {
+    let mut offsets = Vec::new_in(arena);
+    refcounted_field_offsets_help(layout_interner, layout, 0, &mut offsets)?;
+    Some(offsets)
+}
+
+fn refcounted_field_offsets_help<'a>(
+    layout_interner: &STLayoutInterner<'a>,
+    layout: InLayout<'a>,
+    base_offset: u32,
+    offsets: &mut Vec<'a, u32>,
+) -> Option<()> {
+    match layout_interner.get_repr(layout) {
+        LayoutRepr::Struct(field_layouts) => {
+            let mut offset = base_offset;
+            for field_layout in field_layouts.iter() {
+                let (size, alignment) = layout_interner.stack_size_and_alignment(*field_layout);
+                offset = round_up_to_alignment(offset, alignment);
+                refcounted_field_offsets_help(layout_interner, *field_layout, offset, offsets)?;
+                offset += size;
+            }
+        }
+        LayoutRepr::LambdaSet(lambda_set) => {
+            let representation = lambda_set.runtime_representation();
+            refcounted_field_offsets_help(layout_interner, representation, base_offset, offsets)?;
+        }
+        LayoutRepr::Union(UnionLayout::NonRecursive(_)) => {
+            if layout_interner.contains_refcounted(layout) {
+                return None;
+            }
+        }
+        _ => {
+            if layout_interner.contains_refcounted(layout) {
+                offsets.push(base_offset);
+            }
+        }
+    }
+
+    Some(())
+}
+
 pub fn test_helper<'a>(
     env: &CodeGenHelp<'a>,
     ident_ids: &mut IdentIds,
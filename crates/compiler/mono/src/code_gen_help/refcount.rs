@@ -2,14 +2,16 @@
 
 use bumpalo::collections::vec::Vec;
 use bumpalo::collections::CollectIn;
-use roc_error_macros::todo_lambda_erasure;
+use bumpalo::Bump;
+use roc_error_macros::{internal_error, todo_lambda_erasure};
 use roc_module::low_level::{LowLevel, LowLevel::*};
 use roc_module::symbol::{IdentIds, Symbol};
 use roc_target::PtrWidth;
 
 use crate::code_gen_help::let_lowlevel;
 use crate::ir::{
-    BranchInfo, Call, CallType, Expr, JoinPointId, Literal, ModifyRc, Param, Stmt, UpdateModeId,
+    BranchInfo, Call, CallType, CrashTag, Expr, JoinPointId, Literal, ModifyRc, Param, Stmt,
+    UpdateModeId,
 };
 use crate::layout::{
     Builtin, InLayout, Layout, LayoutInterner, LayoutRepr, STLayoutInterner, TagIdIntType,
@@ -37,9 +39,25 @@ pub fn refcount_stmt<'a>(
         ModifyRc::Inc(structure, amount) => {
             let layout_isize = root.layout_isize;
 
+            // Fold any increments of the same structure that come straight after this one.
+            // Nothing can use the structure in between, so one call with the total is equivalent.
+            let mut amount = *amount;
+            let mut following = following;
+            while let Stmt::Refcounting(ModifyRc::Inc(next_structure, next_amount), next) =
+                following
+            {
+                match amount.checked_add(*next_amount) {
+                    Some(total) if next_structure == structure => {
+                        amount = total;
+                        following = next;
+                    }
+                    _ => break,
+                }
+            }
+
             // Define a constant for the amount to increment
             let amount_sym = root.create_symbol(ident_ids, "amount");
-            let amount_expr = Expr::Literal(Literal::Int((*amount as i128).to_ne_bytes()));
+            let amount_expr = Expr::Literal(Literal::Int((amount as i128).to_ne_bytes()));
             let amount_stmt = |next| Stmt::Let(amount_sym, amount_expr, layout_isize, next);
 
             // Call helper proc, passing the Roc structure and constant amount
@@ -106,6 +124,7 @@ pub fn refcount_stmt<'a>(
                             layout,
                             *structure,
                         );
+                        let rc_stmt = bind_hoisted_literals(arena, ctx, rc_stmt);
                         let join = Stmt::Join {
                             id: jp_decref,
                             parameters: &[],
@@ -216,7 +235,6 @@ pub fn refcount_generic<'a>(
             ident_ids,
             ctx,
             layout_interner,
-            layout,
             union_layout,
             structure,
         ),
@@ -234,8 +252,8 @@ pub fn refcount_generic<'a>(
         LayoutRepr::Erased(_) => {
             todo_lambda_erasure!()
         }
-        LayoutRepr::RecursivePointer(_) => unreachable!(
-            "We should never call a refcounting helper on a RecursivePointer layout directly"
+        LayoutRepr::RecursivePointer(_) => internal_error!(
+            "RecursivePointer layouts are replaced by their union before reaching refcount_generic"
         ),
         LayoutRepr::Ptr(_) => {
             unreachable!("We should never call a refcounting helper on a Ptr layout directly")
@@ -253,7 +271,6 @@ pub fn refcount_reset_proc_body<'a>(
 ) -> Stmt<'a> {
     let rc_ptr = root.create_symbol(ident_ids, "rc_ptr");
     let rc = root.create_symbol(ident_ids, "rc");
-    let refcount_1 = root.create_symbol(ident_ids, "refcount_1");
     let is_unique = root.create_symbol(ident_ids, "is_unique");
     let addr = root.create_symbol(ident_ids, "addr");
 
@@ -272,32 +289,17 @@ pub fn refcount_reset_proc_body<'a>(
     let then_stmt = {
         use UnionLayout::*;
 
-        let tag_layouts;
-        let mut null_id = None;
-        match union_layout {
-            NonRecursive(tags) => {
-                tag_layouts = tags;
-            }
-            Recursive(tags) => {
-                tag_layouts = tags;
-            }
-            NonNullableUnwrapped(field_layouts) => {
-                tag_layouts = root.arena.alloc([field_layouts]);
-            }
-            NullableWrapped {
-                other_tags: tags,
-                nullable_id,
-            } => {
-                null_id = Some(nullable_id);
-                tag_layouts = tags;
-            }
-            NullableUnwrapped {
-                other_fields,
-                nullable_id,
-            } => {
-                null_id = Some(nullable_id as TagIdIntType);
-                tag_layouts = root.arena.alloc([other_fields]);
-            }
+        let tag_layouts = match union_layout {
+            NonRecursive(tags)
+            | Recursive(tags)
+            | NullableWrapped {
+                other_tags: tags, ..
+            } => tags,
+            NonNullableUnwrapped(field_layouts)
+            | NullableUnwrapped {
+                other_fields: field_layouts,
+                ..
+            } => root.arena.alloc([field_layouts]),
         };
 
         let tag_id_layout = union_layout.tag_id_layout();
@@ -315,6 +317,11 @@ pub fn refcount_reset_proc_body<'a>(
             )
         };
 
+        let null_branch = union_layout.nullable_id().map(|id| {
+            debug_assert!(union_layout.tag_is_null(id));
+            (id, reset_return_stmt(root, ident_ids, layout, addr))
+        });
+        let ret_stmt = reset_return_stmt(root, ident_ids, layout, addr);
         let rc_contents_stmt = refcount_union_contents(
             root,
             ident_ids,
@@ -322,11 +329,12 @@ pub fn refcount_reset_proc_body<'a>(
             layout_interner,
             union_layout,
             tag_layouts,
-            null_id,
+            null_branch,
             structure,
             tag_id_sym,
             tag_id_layout,
-            Stmt::Ret(addr),
+            layout,
+            ret_stmt,
         );
 
         tag_id_stmt(root.arena.alloc(
@@ -370,6 +378,8 @@ pub fn refcount_reset_proc_body<'a>(
         ret_layout: layout,
     };
 
+    let refcount_1 = refcount_1_literal(root, ident_ids, ctx);
+
     // Uniqueness test
     let is_unique_stmt = {
         let_lowlevel(
@@ -382,29 +392,8 @@ pub fn refcount_reset_proc_body<'a>(
         )
     };
 
-    // Constant for unique refcount
-    let refcount_1_encoded = match root.target_info.ptr_width() {
-        PtrWidth::Bytes4 => i32::MIN as i128,
-        PtrWidth::Bytes8 => i64::MIN as i128,
-    }
-    .to_ne_bytes();
-    let refcount_1_expr = Expr::Literal(Literal::Int(refcount_1_encoded));
-    let refcount_1_stmt = Stmt::Let(
-        refcount_1,
-        refcount_1_expr,
-        root.layout_isize,
-        root.arena.alloc(is_unique_stmt),
-    );
-
     // Refcount value
-    let rc_expr = Expr::ptr_load(root.arena.alloc(rc_ptr));
-
-    let rc_stmt = Stmt::Let(
-        rc,
-        rc_expr,
-        root.layout_isize,
-        root.arena.alloc(refcount_1_stmt),
-    );
+    let rc_stmt = load_refcount(root, rc, rc_ptr, root.arena.alloc(is_unique_stmt));
 
     let mask_lower_bits = match layout_interner.get_repr(layout) {
         LayoutRepr::Union(ul) => ul.stores_tag_id_in_pointer(root.target_info),
@@ -416,12 +405,14 @@ pub fn refcount_reset_proc_body<'a>(
         rc_ptr_from_data_ptr_help(
             root,
             ident_ids,
+            ctx,
             structure,
             rc_ptr,
             mask_lower_bits,
             root.arena.alloc(rc_stmt),
             addr,
             recursion_ptr,
+            layout,
         )
     };
 
@@ -438,7 +429,6 @@ pub fn refcount_resetref_proc_body<'a>(
 ) -> Stmt<'a> {
     let rc_ptr = root.create_symbol(ident_ids, "rc_ptr");
     let rc = root.create_symbol(ident_ids, "rc");
-    let refcount_1 = root.create_symbol(ident_ids, "refcount_1");
     let is_unique = root.create_symbol(ident_ids, "is_unique");
     let addr = root.create_symbol(ident_ids, "addr");
 
@@ -454,7 +444,7 @@ pub fn refcount_resetref_proc_body<'a>(
         layout_interner.insert_direct_no_semantic(LayoutRepr::RecursivePointer(layout));
 
     // Reset structure is unique. Return a pointer to the allocation.
-    let then_stmt = Stmt::Ret(addr);
+    let then_stmt = reset_return_stmt(root, ident_ids, layout, addr);
 
     // Reset structure is not unique. Decrement it and return a NULL pointer.
     let else_stmt = {
@@ -495,6 +485,8 @@ pub fn refcount_resetref_proc_body<'a>(
         root.arena.alloc(else_stmt),
     );
 
+    let refcount_1 = refcount_1_literal(root, ident_ids, ctx);
+
     // Uniqueness test
     let is_unique_stmt = {
         let_lowlevel(
@@ -507,29 +499,8 @@ pub fn refcount_resetref_proc_body<'a>(
         )
     };
 
-    // Constant for unique refcount
-    let refcount_1_encoded = match root.target_info.ptr_width() {
-        PtrWidth::Bytes4 => i32::MIN as i128,
-        PtrWidth::Bytes8 => i64::MIN as i128,
-    }
-    .to_ne_bytes();
-    let refcount_1_expr = Expr::Literal(Literal::Int(refcount_1_encoded));
-    let refcount_1_stmt = Stmt::Let(
-        refcount_1,
-        refcount_1_expr,
-        root.layout_isize,
-        root.arena.alloc(is_unique_stmt),
-    );
-
     // Refcount value
-    let rc_expr = Expr::ptr_load(root.arena.alloc(rc_ptr));
-
-    let rc_stmt = Stmt::Let(
-        rc,
-        rc_expr,
-        root.layout_isize,
-        root.arena.alloc(refcount_1_stmt),
-    );
+    let rc_stmt = load_refcount(root, rc, rc_ptr, root.arena.alloc(is_unique_stmt));
 
     let mask_lower_bits = match layout_interner.get_repr(layout) {
         LayoutRepr::Union(ul) => ul.stores_tag_id_in_pointer(root.target_info),
@@ -541,18 +512,227 @@ pub fn refcount_resetref_proc_body<'a>(
         rc_ptr_from_data_ptr_help(
             root,
             ident_ids,
+            ctx,
             structure,
             rc_ptr,
             mask_lower_bits,
             root.arena.alloc(rc_stmt),
             addr,
             recursion_ptr,
+            layout,
         )
     };
 
     rc_ptr_stmt
 }
 
+/// Generate a procedure that checks whether a value's allocation has a refcount of 1
+pub fn is_unique_proc_body<'a>(
+    root: &mut CodeGenHelp<'a>,
+    ident_ids: &mut IdentIds,
+    ctx: &mut Context<'a>,
+    layout_interner: &mut STLayoutInterner<'a>,
+    layout: InLayout<'a>,
+    structure: Symbol,
+) -> Stmt<'a> {
+    let arena = root.arena;
+    let layout_isize = root.layout_isize;
+
+    let is_unique = root.create_symbol(ident_ids, "is_unique");
+    let ret_is_unique = |data_ptr| {
+        // The Zig builtin also treats a null pointer, like an empty list's, as unique
+        let_lowlevel(
+            arena,
+            LAYOUT_BOOL,
+            is_unique,
+            RefCountIsUnique,
+            &[data_ptr],
+            arena.alloc(Stmt::Ret(is_unique)),
+        )
+    };
+
+    // Seamless slices store their allocation's data pointer shifted right by one, in the last word.
+    // That leaves the sign bit free to mark the value as a slice.
+    let slice_data_addr = root.create_symbol(ident_ids, "slice_data_addr");
+    let slice_data_ptr = root.create_symbol(ident_ids, "slice_data_ptr");
+    let slice_data_ptr_stmt = |ctx: &mut Context<'a>, ident_ids: &mut IdentIds, last_word| {
+        let one = hoisted_literal(root, ident_ids, ctx, "one", 1, layout_isize);
+        move |next| {
+            let_lowlevel(
+                arena,
+                layout_isize,
+                slice_data_addr,
+                NumShiftLeftBy,
+                &[last_word, one],
+                arena.alloc(let_lowlevel(
+                    arena,
+                    Layout::OPAQUE_PTR,
+                    slice_data_ptr,
+                    PtrCast,
+                    &[slice_data_addr],
+                    next,
+                )),
+            )
+        }
+    };
+
+    match layout_interner.get_repr(layout) {
+        LayoutRepr::Builtin(Builtin::Str) => {
+            let zero = hoisted_literal(root, ident_ids, ctx, "zero", 0, layout_isize);
+            let field_layouts = arena.alloc([Layout::OPAQUE_PTR, layout_isize, layout_isize]);
+            let field = |index| Expr::StructAtIndex {
+                index,
+                field_layouts,
+                structure,
+            };
+
+            let chars = root.create_symbol(ident_ids, "chars");
+            let length = root.create_symbol(ident_ids, "length");
+            let last_word = root.create_symbol(ident_ids, "last_word");
+            let is_big_str = root.create_symbol(ident_ids, "is_big_str");
+            let is_slice = root.create_symbol(ident_ids, "is_slice");
+            let last_word_int = root.create_symbol(ident_ids, "last_word_int");
+            let data_ptr_stmt = slice_data_ptr_stmt(ctx, ident_ids, last_word_int);
+
+            // A small string has no allocation to share
+            let small_str = root.create_symbol(ident_ids, "small_str");
+            let true_stmt = Stmt::Let(
+                small_str,
+                Expr::Literal(Literal::Bool(true)),
+                LAYOUT_BOOL,
+                arena.alloc(Stmt::Ret(small_str)),
+            );
+
+            let slice_branch = let_lowlevel(
+                arena,
+                layout_isize,
+                last_word_int,
+                PtrCast,
+                &[last_word],
+                arena.alloc(data_ptr_stmt(arena.alloc(ret_is_unique(slice_data_ptr)))),
+            );
+
+            let big_str_branch = Stmt::Let(
+                length,
+                field(1),
+                layout_isize,
+                arena.alloc(let_lowlevel(
+                    arena,
+                    LAYOUT_BOOL,
+                    is_slice,
+                    NumLt,
+                    &[length, zero],
+                    arena.alloc(Stmt::if_then_else(
+                        arena,
+                        is_slice,
+                        LAYOUT_BOOL,
+                        slice_branch,
+                        arena.alloc(Stmt::Let(
+                            chars,
+                            field(0),
+                            Layout::OPAQUE_PTR,
+                            arena.alloc(ret_is_unique(chars)),
+                        )),
+                    )),
+                )),
+            );
+
+            Stmt::Let(
+                last_word,
+                field(2),
+                layout_isize,
+                arena.alloc(let_lowlevel(
+                    arena,
+                    LAYOUT_BOOL,
+                    is_big_str,
+                    NumGte,
+                    &[last_word, zero],
+                    arena.alloc(Stmt::if_then_else(
+                        arena,
+                        is_big_str,
+                        LAYOUT_BOOL,
+                        big_str_branch,
+                        arena.alloc(true_stmt),
+                    )),
+                )),
+            )
+        }
+
+        LayoutRepr::Builtin(Builtin::List(elem_layout)) => {
+            let zero = hoisted_literal(root, ident_ids, ctx, "zero", 0, layout_isize);
+            let ptr_layout =
+                layout_interner.insert_direct_no_semantic(LayoutRepr::Ptr(elem_layout));
+            let field_layouts = arena.alloc([ptr_layout, layout_isize, layout_isize]);
+            let field = |index| Expr::StructAtIndex {
+                index,
+                field_layouts,
+                structure,
+            };
+
+            let first_element = root.create_symbol(ident_ids, "first_element");
+            let capacity = root.create_symbol(ident_ids, "capacity");
+            let is_slice = root.create_symbol(ident_ids, "is_slice");
+            let data_ptr_stmt = slice_data_ptr_stmt(ctx, ident_ids, capacity);
+
+            Stmt::Let(
+                capacity,
+                field(2),
+                layout_isize,
+                arena.alloc(let_lowlevel(
+                    arena,
+                    LAYOUT_BOOL,
+                    is_slice,
+                    NumLt,
+                    &[capacity, zero],
+                    arena.alloc(Stmt::if_then_else(
+                        arena,
+                        is_slice,
+                        LAYOUT_BOOL,
+                        data_ptr_stmt(arena.alloc(ret_is_unique(slice_data_ptr))),
+                        arena.alloc(Stmt::Let(
+                            first_element,
+                            field(0),
+                            ptr_layout,
+                            arena.alloc(ret_is_unique(first_element)),
+                        )),
+                    )),
+                )),
+            )
+        }
+
+        // The value is a pointer to the allocation, possibly with a tag id in its lower bits
+        LayoutRepr::Union(union_layout)
+            if !matches!(union_layout, UnionLayout::NonRecursive(_)) =>
+        {
+            ret_is_unique(structure)
+        }
+        LayoutRepr::RecursivePointer(_) => ret_is_unique(structure),
+
+        // Anything else is stored inline, with no allocation of its own to share
+        _ => {
+            let inline = root.create_symbol(ident_ids, "inline");
+            Stmt::Let(
+                inline,
+                Expr::Literal(Literal::Bool(true)),
+                LAYOUT_BOOL,
+                arena.alloc(Stmt::Ret(inline)),
+            )
+        }
+    }
+}
+
+/// Return the address of a reset allocation, with the union's layout for the reuse to fill in
+fn reset_return_stmt<'a>(
+    root: &CodeGenHelp<'a>,
+    ident_ids: &mut IdentIds,
+    layout: InLayout<'a>,
+    addr: Symbol,
+) -> Stmt<'a> {
+    let reset_ptr = root.create_symbol(ident_ids, "reset_ptr");
+    let ret_stmt = root.arena.alloc(Stmt::Ret(reset_ptr));
+    let_lowlevel(root.arena, layout, reset_ptr, PtrCast, &[addr], ret_stmt)
+}
+
 fn rc_return_stmt<'a>(
     root: &CodeGenHelp<'a>,
     ident_ids: &mut IdentIds,
@@ -579,30 +759,22 @@ fn refcount_args<'a>(root: &CodeGenHelp<'a>, ctx: &Context<'a>, structure: Symbo
 fn rc_ptr_from_data_ptr_help<'a>(
     root: &CodeGenHelp<'a>,
     ident_ids: &mut IdentIds,
+    ctx: &mut Context<'a>,
     structure: Symbol,
     rc_ptr_sym: Symbol,
     mask_lower_bits: bool,
     following: &'a Stmt<'a>,
     addr_sym: Symbol,
     recursion_ptr: InLayout<'a>,
+    ret_layout: InLayout<'a>,
 ) -> Stmt<'a> {
-    // symbol of a pointer with any tag id bits cleared
-    let cleared_sym = if mask_lower_bits {
-        root.create_symbol(ident_ids, "cleared")
+    // symbol of the address with any tag id bits still set
+    let tagged_addr_sym = if mask_lower_bits {
+        root.create_symbol(ident_ids, "tagged_addr")
     } else {
-        structure
+        addr_sym
     };
 
-    let clear_tag_id_expr = Expr::Call(Call {
-        call_type: CallType::LowLevel {
-            op: LowLevel::PtrClearTagId,
-            update_mode: UpdateModeId::BACKEND_DUMMY,
-        },
-        arguments: root.arena.alloc([structure]),
-    });
-    let clear_tag_id_stmt =
-        |next| Stmt::Let(cleared_sym, clear_tag_id_expr, root.layout_isize, next);
-
     // Typecast the structure pointer to an integer
     // Backends expect a number Layout to choose the right "subtract" instruction
     let as_int_expr = Expr::Call(Call {
@@ -610,16 +782,20 @@ fn rc_ptr_from_data_ptr_help<'a>(
             op: LowLevel::PtrCast,
             update_mode: UpdateModeId::BACKEND_DUMMY,
         },
-        arguments: root.arena.alloc([cleared_sym]),
+        arguments: root.arena.alloc([structure]),
     });
-    let as_int_stmt = |next| Stmt::Let(addr_sym, as_int_expr, root.layout_isize, next);
+    let as_int_stmt = |next| Stmt::Let(tagged_addr_sym, as_int_expr, root.layout_isize, next);
 
     // Pointer size constant
-    let ptr_size_sym = root.create_symbol(ident_ids, "ptr_size");
-    let ptr_size_expr = Expr::Literal(Literal::Int(
-        (root.target_info.ptr_width() as i128).to_ne_bytes(),
-    ));
-    let ptr_size_stmt = |next| Stmt::Let(ptr_size_sym, ptr_size_expr, root.layout_isize, next);
+    let ptr_size = root.target_info.ptr_width() as i128;
+    let ptr_size_sym = hoisted_literal(
+        root,
+        ident_ids,
+        ctx,
+        "ptr_size",
+        ptr_size,
+        root.layout_isize,
+    );
 
     // Refcount address
     let rc_addr_sym = root.create_symbol(ident_ids, "rc_addr");
@@ -640,27 +816,185 @@ fn rc_ptr_from_data_ptr_help<'a>(
         },
         arguments: root.arena.alloc([rc_addr_sym]),
     });
-    let cast_stmt = |next| Stmt::Let(rc_ptr_sym, cast_expr, recursion_ptr, next);
+    let cast_stmt = Stmt::Let(rc_ptr_sym, cast_expr, recursion_ptr, following);
 
-    let body = as_int_stmt(root.arena.alloc(
-        //
-        ptr_size_stmt(root.arena.alloc(
-            //
-            sub_stmt(root.arena.alloc(
-                //
-                cast_stmt(root.arena.alloc(
-                    //
-                    following,
+    // Crash unless the refcount address is aligned to a word, like refcounts always are.
+    // A misaligned address means the layout we computed doesn't match the allocation.
+    let checked_cast_stmt = if root.check_refcounts {
+        let layout_usize = Layout::usize(root.target_info);
+        let mask_sym =
+            hoisted_literal(root, ident_ids, ctx, "ptr_mask", ptr_size - 1, layout_usize);
+        let zero_sym = hoisted_literal(root, ident_ids, ctx, "zero", 0, layout_usize);
+
+        let misaligned_bits_sym = root.create_symbol(ident_ids, "misaligned_bits");
+        let is_aligned_sym = root.create_symbol(ident_ids, "is_aligned");
+        let crash = crash_stmt(root, ident_ids, "Refcount pointer is misaligned");
+
+        let_lowlevel(
+            root.arena,
+            layout_usize,
+            misaligned_bits_sym,
+            NumBitwiseAnd,
+            &[rc_addr_sym, mask_sym],
+            root.arena.alloc(let_lowlevel(
+                root.arena,
+                LAYOUT_BOOL,
+                is_aligned_sym,
+                Eq,
+                &[misaligned_bits_sym, zero_sym],
+                root.arena.alloc(Stmt::if_then_else(
+                    root.arena,
+                    is_aligned_sym,
+                    ret_layout,
+                    cast_stmt,
+                    root.arena.alloc(crash),
                 )),
             )),
-        )),
+        )
+    } else {
+        cast_stmt
+    };
+
+    let body = sub_stmt(root.arena.alloc(
+        //
+        checked_cast_stmt,
     ));
 
-    if mask_lower_bits {
-        clear_tag_id_stmt(root.arena.alloc(body))
+    // Clear the tag id bits. There are as many as fit in the alignment of a pointer.
+    let body = if mask_lower_bits {
+        let tag_mask_sym = hoisted_literal(
+            root,
+            ident_ids,
+            ctx,
+            "tag_mask",
+            -ptr_size,
+            root.layout_isize,
+        );
+        let_lowlevel(
+            root.arena,
+            root.layout_isize,
+            addr_sym,
+            NumBitwiseAnd,
+            &[tagged_addr_sym, tag_mask_sym],
+            root.arena.alloc(body),
+        )
     } else {
         body
+    };
+
+    as_int_stmt(root.arena.alloc(body))
+}
+
+/// The encoded refcount of a unique allocation, which is `isize::MIN` on the target
+fn refcount_1_literal<'a>(
+    root: &CodeGenHelp<'a>,
+    ident_ids: &mut IdentIds,
+    ctx: &mut Context<'a>,
+) -> Symbol {
+    let refcount_1_encoded = match root.target_info.ptr_width() {
+        PtrWidth::Bytes4 => i32::MIN as i128,
+        PtrWidth::Bytes8 => i64::MIN as i128,
+    };
+    hoisted_literal(
+        root,
+        ident_ids,
+        ctx,
+        "refcount_1",
+        refcount_1_encoded,
+        root.layout_isize,
+    )
+}
+
+/// Load the refcount that `rc_ptr` points to, as a signed integer
+fn load_refcount<'a>(
+    root: &CodeGenHelp<'a>,
+    rc: Symbol,
+    rc_ptr: Symbol,
+    following: &'a Stmt<'a>,
+) -> Stmt<'a> {
+    let rc_expr = Expr::ptr_load(root.arena.alloc(rc_ptr));
+    Stmt::Let(rc, rc_expr, root.layout_isize, following)
+}
+
+/// Crash unless a recursive union pointer is aligned, ignoring any tag id stored in its low bits.
+/// Checked before reading through the pointer, so a corrupted structure is caught where it's followed.
+/// The refcount address is checked in `rc_ptr_from_data_ptr_help`, and it is aligned if the data is.
+fn check_union_ptr_alignment<'a>(
+    root: &CodeGenHelp<'a>,
+    ident_ids: &mut IdentIds,
+    ctx: &mut Context<'a>,
+    union_layout: UnionLayout<'a>,
+    structure: Symbol,
+    following: Stmt<'a>,
+) -> Stmt<'a> {
+    if !root.check_refcounts {
+        return following;
     }
+
+    let addr = root.create_symbol(ident_ids, "union_addr");
+    let rc_ptr = root.create_symbol(ident_ids, "union_rc_ptr");
+
+    rc_ptr_from_data_ptr_help(
+        root,
+        ident_ids,
+        ctx,
+        structure,
+        rc_ptr,
+        union_layout.stores_tag_id_in_pointer(root.target_info),
+        root.arena.alloc(following),
+        addr,
+        Layout::OPAQUE_PTR,
+        LAYOUT_UNIT,
+    )
+}
+
+fn crash_stmt<'a>(root: &CodeGenHelp<'a>, ident_ids: &mut IdentIds, msg: &'a str) -> Stmt<'a> {
+    let msg_sym = root.create_symbol(ident_ids, "crash_msg");
+    Stmt::Let(
+        msg_sym,
+        Expr::Literal(Literal::Str(msg)),
+        Layout::STR,
+        root.arena.alloc(Stmt::Crash(msg_sym, CrashTag::Roc)),
+    )
+}
+
+/// Get a symbol for an integer literal that is bound once at the top of the current helper body.
+/// Helpers like the tail-recursive union decrement loop use the same constants on every iteration.
+fn hoisted_literal<'a>(
+    root: &CodeGenHelp<'a>,
+    ident_ids: &mut IdentIds,
+    ctx: &mut Context<'a>,
+    name: &str,
+    value: i128,
+    layout: InLayout<'a>,
+) -> Symbol {
+    let found = ctx
+        .hoisted_literals
+        .iter()
+        .find(|(_, v, l)| *v == value && *l == layout);
+
+    if let Some((sym, _, _)) = found {
+        return *sym;
+    }
+
+    let sym = root.create_symbol(ident_ids, name);
+    ctx.hoisted_literals.push((sym, value, layout));
+    sym
+}
+
+/// Bind all literals hoisted while generating `body`, and reset the pool for the next body.
+pub fn bind_hoisted_literals<'a>(
+    arena: &'a Bump,
+    ctx: &mut Context<'a>,
+    body: Stmt<'a>,
+) -> Stmt<'a> {
+    ctx.hoisted_literals
+        .drain(..)
+        .rev()
+        .fold(body, |next, (sym, value, layout)| {
+            let expr = Expr::Literal(Literal::Int(value.to_ne_bytes()));
+            Stmt::Let(sym, expr, layout, arena.alloc(next))
+        })
 }
 
 enum Pointer {
@@ -704,9 +1038,14 @@ fn modify_refcount<'a>(
                 Pointer::ToRefcount(s) => (LowLevel::RefCountDecRcPtr, s),
             };
 
-            let alignment_sym = root.create_symbol(ident_ids, "alignment");
-            let alignment_expr = Expr::Literal(Literal::Int((alignment as i128).to_ne_bytes()));
-            let alignment_stmt = |next| Stmt::Let(alignment_sym, alignment_expr, LAYOUT_U32, next);
+            let alignment_sym = hoisted_literal(
+                root,
+                ident_ids,
+                ctx,
+                "alignment",
+                alignment as i128,
+                LAYOUT_U32,
+            );
 
             let zig_call_expr = Expr::Call(Call {
                 call_type: CallType::LowLevel {
@@ -717,17 +1056,215 @@ fn modify_refcount<'a>(
             });
             let zig_call_stmt = Stmt::Let(zig_call_result, zig_call_expr, LAYOUT_UNIT, following);
 
-            alignment_stmt(root.arena.alloc(
-                //
-                zig_call_stmt,
-            ))
+            if root.check_refcounts {
+                check_refcount_live(root, ident_ids, ctx, op, ptr, zig_call_stmt)
+            } else {
+                zig_call_stmt
+            }
         }
 
         _ => unreachable!(),
     }
 }
 
+/// Crash before decrementing a refcount that can't belong to a live allocation.
+/// Refcounts count up from `isize::MIN`, or are 0 for read-only values, so a positive one
+/// means something went wrong. If it's the marker from `mark_while_freeing`, the allocation
+/// is being freed and we got back to it through a reference cycle. Otherwise, the allocation
+/// was already freed (or overwritten) and this is a double free.
+fn check_refcount_live<'a>(
+    root: &CodeGenHelp<'a>,
+    ident_ids: &mut IdentIds,
+    ctx: &mut Context<'a>,
+    op: LowLevel,
+    ptr: Symbol,
+    following: Stmt<'a>,
+) -> Stmt<'a> {
+    let arena = root.arena;
+    let layout_isize = root.layout_isize;
+
+    let jp_checked = JoinPointId(root.create_symbol(ident_ids, "jp_rc_checked"));
+    let zero = hoisted_literal(root, ident_ids, ctx, "zero", 0, layout_isize);
+
+    let freeing = freeing_literal(root, ident_ids, ctx);
+
+    let rc = root.create_symbol(ident_ids, "rc");
+    let is_live = root.create_symbol(ident_ids, "is_live");
+    let is_freeing = root.create_symbol(ident_ids, "is_freeing");
+    let crash = let_lowlevel(
+        arena,
+        LAYOUT_BOOL,
+        is_freeing,
+        Eq,
+        &[rc, freeing],
+        arena.alloc(Stmt::if_then_else(
+            arena,
+            is_freeing,
+            LAYOUT_UNIT,
+            crash_stmt(
+                root,
+                ident_ids,
+                "Reference cycle: a value was decremented while it was being freed",
+            ),
+            arena.alloc(crash_stmt(
+                root,
+                ident_ids,
+                "Refcount underflow: a value was decremented after it was freed",
+            )),
+        )),
+    );
+    let check_rc = |rc_ptr| {
+        load_refcount(
+            root,
+            rc,
+            rc_ptr,
+            arena.alloc(let_lowlevel(
+                arena,
+                LAYOUT_BOOL,
+                is_live,
+                NumLte,
+                &[rc, zero],
+                arena.alloc(Stmt::if_then_else(
+                    arena,
+                    is_live,
+                    LAYOUT_UNIT,
+                    Stmt::Jump(jp_checked, &[]),
+                    arena.alloc(crash),
+                )),
+            )),
+        )
+    };
+
+    let check_stmt = if op == LowLevel::RefCountDecRcPtr {
+        check_rc(ptr)
+    } else {
+        // Like the Zig builtin, clear any tag id bits and skip null pointers, e.g. of an empty Str
+        let addr = root.create_symbol(ident_ids, "addr");
+        let is_null = root.create_symbol(ident_ids, "is_null");
+        let rc_ptr = root.create_symbol(ident_ids, "rc_ptr");
+
+        let null_check_stmt = let_lowlevel(
+            arena,
+            LAYOUT_BOOL,
+            is_null,
+            Eq,
+            &[addr, zero],
+            arena.alloc(Stmt::if_then_else(
+                arena,
+                is_null,
+                LAYOUT_UNIT,
+                Stmt::Jump(jp_checked, &[]),
+                arena.alloc(check_rc(rc_ptr)),
+            )),
+        );
+
+        rc_ptr_from_data_ptr_help(
+            root,
+            ident_ids,
+            ctx,
+            ptr,
+            rc_ptr,
+            true,
+            arena.alloc(null_check_stmt),
+            addr,
+            Layout::OPAQUE_PTR,
+            LAYOUT_UNIT,
+        )
+    };
+
+    Stmt::Join {
+        id: jp_checked,
+        parameters: &[],
+        body: arena.alloc(following),
+        remainder: arena.alloc(check_stmt),
+    }
+}
+
+/// The refcount that `mark_while_freeing` stores in an allocation while freeing its fields
+fn freeing_literal<'a>(
+    root: &CodeGenHelp<'a>,
+    ident_ids: &mut IdentIds,
+    ctx: &mut Context<'a>,
+) -> Symbol {
+    hoisted_literal(root, ident_ids, ctx, "freeing", 1, root.layout_isize)
+}
+
+/// While the fields of a unique allocation are being decremented, overwrite its refcount with
+/// a positive marker, which no live value can have. If a reference cycle leads back to the
+/// allocation, the nested Dec finds the marker in `check_refcount_live` and crashes, instead of
+/// decrementing the same fields again until the stack overflows.
+/// `contents` must jump to `jp_visited` when it's done. That restores the refcount to one,
+/// so that the allocation can be freed as usual, and continues at `jp_done`.
+/// Any `visited_params` of `jp_visited` are passed on to `jp_done`.
+///
+/// This only catches cycles that come back while the fields are being visited. The spine loop in
+/// `refcount_union_tailrec` marks each node while it visits the other fields, but it frees the
+/// node before following the tail field. A cycle through the tail field reads freed memory
+/// instead, and may or may not be caught.
+fn mark_while_freeing<'a>(
+    root: &CodeGenHelp<'a>,
+    ident_ids: &mut IdentIds,
+    ctx: &mut Context<'a>,
+    layout_interner: &mut STLayoutInterner<'a>,
+    union_layout: UnionLayout<'a>,
+    structure: Symbol,
+    jp_visited: JoinPointId,
+    visited_params: &'a [Param<'a>],
+    jp_done: JoinPointId,
+    ret_layout: InLayout<'a>,
+    contents: Stmt<'a>,
+) -> Stmt<'a> {
+    let arena = root.arena;
+    let layout_isize = root.layout_isize;
+
+    let refcount_1 = refcount_1_literal(root, ident_ids, ctx);
+    let freeing = freeing_literal(root, ident_ids, ctx);
+
+    let rc_ptr = root.create_symbol(ident_ids, "rc_ptr");
+    let addr = root.create_symbol(ident_ids, "addr");
+    let mark = root.create_symbol(ident_ids, "mark");
+    let unmark = root.create_symbol(ident_ids, "unmark");
+    let rc_ptr_layout = layout_interner.insert_direct_no_semantic(LayoutRepr::Ptr(layout_isize));
+
+    let done_args = arena.alloc_slice_fill_iter(visited_params.iter().map(|p| p.symbol));
+    let restore_stmt = Stmt::Let(
+        unmark,
+        Expr::ptr_store(arena.alloc([rc_ptr, refcount_1])),
+        LAYOUT_UNIT,
+        arena.alloc(Stmt::Jump(jp_done, done_args)),
+    );
+
+    let mark_stmt = Stmt::Let(
+        mark,
+        Expr::ptr_store(arena.alloc([rc_ptr, freeing])),
+        LAYOUT_UNIT,
+        arena.alloc(Stmt::Join {
+            id: jp_visited,
+            parameters: visited_params,
+            body: arena.alloc(restore_stmt),
+            remainder: arena.alloc(contents),
+        }),
+    );
+
+    rc_ptr_from_data_ptr_help(
+        root,
+        ident_ids,
+        ctx,
+        structure,
+        rc_ptr,
+        union_layout.stores_tag_id_in_pointer(root.target_info),
+        arena.alloc(mark_stmt),
+        addr,
+        rc_ptr_layout,
+        ret_layout,
+    )
+}
+
 /// Generate a procedure to modify the reference count of a Str
+///
+/// Strings that must never be freed, like interned constants, store the "immortal" refcount
+/// (`REFCOUNT_MAX_ISIZE` in utils.zig, which is 0). The Zig builtins skip those on both inc
+/// and dec, so this helper can call them unconditionally.
 fn refcount_str<'a>(
     root: &CodeGenHelp<'a>,
     ident_ids: &mut IdentIds,
@@ -747,10 +1284,7 @@ fn refcount_str<'a>(
     };
     let last_word_stmt = |next| Stmt::Let(last_word, last_word_expr, layout_isize, next);
 
-    // Zero
-    let zero = root.create_symbol(ident_ids, "zero");
-    let zero_expr = Expr::Literal(Literal::Int(0i128.to_ne_bytes()));
-    let zero_stmt = |next| Stmt::Let(zero, zero_expr, layout_isize, next);
+    let zero = hoisted_literal(root, ident_ids, ctx, "zero", 0, layout_isize);
 
     // is_big_str = (last_word >= 0);
     // Treat last word as isize so that the small string flag is the same as the sign bit
@@ -793,9 +1327,7 @@ fn refcount_str<'a>(
 
     let return_unit = arena.alloc(rc_return_stmt(root, ident_ids, ctx));
 
-    let one = root.create_symbol(ident_ids, "one");
-    let one_expr = Expr::Literal(Literal::Int(1i128.to_ne_bytes()));
-    let one_stmt = |next| Stmt::Let(one, one_expr, layout_isize, next);
+    let one = hoisted_literal(root, ident_ids, ctx, "one", 1, layout_isize);
 
     let data_ptr_int = root.create_symbol(ident_ids, "data_ptr_int");
     let data_ptr_int_stmt = |next| {
@@ -822,21 +1354,18 @@ fn refcount_str<'a>(
     };
 
     // when the string is a slice, the capacity field is a pointer to the refcount
-    let slice_branch = one_stmt(arena.alloc(
+    let slice_branch = data_ptr_int_stmt(arena.alloc(
         //
-        data_ptr_int_stmt(arena.alloc(
+        data_ptr_stmt(arena.alloc(
             //
-            data_ptr_stmt(arena.alloc(
-                //
-                modify_refcount(
-                    root,
-                    ident_ids,
-                    ctx,
-                    Pointer::ToData(data_ptr),
-                    alignment,
-                    return_unit,
-                ),
-            )),
+            modify_refcount(
+                root,
+                ident_ids,
+                ctx,
+                Pointer::ToData(data_ptr),
+                alignment,
+                return_unit,
+            ),
         )),
     ));
 
@@ -847,7 +1376,8 @@ fn refcount_str<'a>(
         field_layouts,
         structure: string,
     };
-    let string_chars_stmt = |next| Stmt::Let(string_chars, string_chars_expr, layout_isize, next);
+    let string_chars_stmt =
+        |next| Stmt::Let(string_chars, string_chars_expr, Layout::OPAQUE_PTR, next);
 
     let modify_refcount_stmt = modify_refcount(
         root,
@@ -897,12 +1427,9 @@ fn refcount_str<'a>(
     // Combine the statements in sequence
     last_word_stmt(arena.alloc(
         //
-        zero_stmt(arena.alloc(
+        is_big_str_stmt(arena.alloc(
             //
-            is_big_str_stmt(arena.alloc(
-                //
-                if_big_stmt,
-            )),
+            if_big_stmt,
         )),
     ))
 }
@@ -928,10 +1455,7 @@ fn refcount_list<'a>(
     let len = root.create_symbol(ident_ids, "len");
     let len_stmt = |next| let_lowlevel(arena, layout_isize, len, ListLen, &[structure], next);
 
-    // let zero = 0
-    let zero = root.create_symbol(ident_ids, "zero");
-    let zero_expr = Expr::Literal(Literal::Int(0i128.to_ne_bytes()));
-    let zero_stmt = |next| Stmt::Let(zero, zero_expr, layout_isize, next);
+    let zero = hoisted_literal(root, ident_ids, ctx, "zero", 0, layout_isize);
 
     // let is_empty = lowlevel Eq len zero
     let is_empty = root.create_symbol(ident_ids, "is_empty");
@@ -969,63 +1493,20 @@ fn refcount_list<'a>(
     };
     let first_element_stmt = |next| Stmt::Let(first_element, first_element_expr, ptr_layout, next);
 
-    let jp_elements = JoinPointId(root.create_symbol(ident_ids, "jp_elements"));
-    let data_pointer = root.create_symbol(ident_ids, "data_pointer");
-    let param_data_pointer = Param {
-        symbol: data_pointer,
-        layout: Layout::OPAQUE_PTR,
-    };
-
-    let first_element_pointer = root.create_symbol(ident_ids, "first_element_pointer");
-    let param_first_element_pointer = Param {
-        symbol: first_element_pointer,
-        layout: Layout::OPAQUE_PTR,
-    };
-
-    // one = 1
-    let one = root.create_symbol(ident_ids, "one");
-    let one_expr = Expr::Literal(Literal::Int(1i128.to_ne_bytes()));
-    let one_stmt = |next| Stmt::Let(one, one_expr, layout_isize, next);
+    let one = hoisted_literal(root, ident_ids, ctx, "one", 1, layout_isize);
 
     let slice_data_pointer = root.create_symbol(ident_ids, "slice_data_pointer");
     let slice_data_pointer_stmt = move |next| {
-        one_stmt(arena.alloc(
-            //
-            let_lowlevel(
-                arena,
-                layout_isize,
-                slice_data_pointer,
-                LowLevel::NumShiftLeftBy,
-                &[capacity, one],
-                arena.alloc(next),
-            ),
-        ))
+        let_lowlevel(
+            arena,
+            layout_isize,
+            slice_data_pointer,
+            LowLevel::NumShiftLeftBy,
+            &[capacity, one],
+            arena.alloc(next),
+        )
     };
 
-    let slice_branch = slice_data_pointer_stmt(
-        //
-        Stmt::Jump(
-            jp_elements,
-            arena.alloc([slice_data_pointer, first_element]),
-        ),
-    );
-
-    let list_branch = arena.alloc(
-        //
-        Stmt::Jump(jp_elements, arena.alloc([first_element, first_element])),
-    );
-
-    let switch_slice_list = arena.alloc(first_element_stmt(arena.alloc(
-        //
-        Stmt::if_then_else(
-            root.arena,
-            is_slice,
-            Layout::UNIT,
-            slice_branch,
-            arena.alloc(list_branch),
-        ),
-    )));
-
     //
     // modify refcount of the list and its elements
     // (elements first, to avoid use-after-free for when decrementing)
@@ -1037,14 +1518,104 @@ fn refcount_list<'a>(
     );
 
     let ret_stmt = arena.alloc(rc_return_stmt(root, ident_ids, ctx));
-    let mut modify_refcount_stmt =
-        |ptr| modify_refcount(root, ident_ids, ctx, ptr, alignment, ret_stmt);
-
-    let modify_list = modify_refcount_stmt(Pointer::ToData(data_pointer));
 
+    // The element loop strides by the element size, so it can't step over zero-sized elements.
+    // Those can't hold a pointer anyway, so there is nothing to visit.
     let is_relevant_op = ctx.op.is_dec() || ctx.op.is_inc();
-    let modify_elems_and_list = if is_relevant_op && layout_interner.is_refcounted(elem_layout) {
-        refcount_list_elems(
+    let has_refcounted_elems = layout_interner.stack_size(elem_layout) > 0
+        && layout_interner.contains_refcounted(elem_layout);
+
+    let modify_slice_or_list = if !(is_relevant_op && has_refcounted_elems) {
+        // Nothing to do for the elements, so each branch can modify the allocation directly
+        let modify_slice = modify_refcount(
+            root,
+            ident_ids,
+            ctx,
+            Pointer::ToData(slice_data_pointer),
+            alignment,
+            ret_stmt,
+        );
+        let modify_list = modify_refcount(
+            root,
+            ident_ids,
+            ctx,
+            Pointer::ToData(first_element),
+            alignment,
+            ret_stmt,
+        );
+
+        first_element_stmt(arena.alloc(
+            //
+            Stmt::if_then_else(
+                arena,
+                is_slice,
+                Layout::UNIT,
+                slice_data_pointer_stmt(modify_slice),
+                arena.alloc(modify_list),
+            ),
+        ))
+    } else {
+        let jp_elements = JoinPointId(root.create_symbol(ident_ids, "jp_elements"));
+        let data_pointer = root.create_symbol(ident_ids, "data_pointer");
+        let param_data_pointer = Param {
+            symbol: data_pointer,
+            layout: Layout::OPAQUE_PTR,
+        };
+
+        let first_element_pointer = root.create_symbol(ident_ids, "first_element_pointer");
+        let param_first_element_pointer = Param {
+            symbol: first_element_pointer,
+            layout: ptr_layout,
+        };
+
+        // Both branches pass the data pointer with the layout of the joinpoint parameter
+        let slice_data_ptr = root.create_symbol(ident_ids, "slice_data_ptr");
+        let slice_branch = slice_data_pointer_stmt(let_lowlevel(
+            arena,
+            Layout::OPAQUE_PTR,
+            slice_data_ptr,
+            PtrCast,
+            &[slice_data_pointer],
+            arena.alloc(Stmt::Jump(
+                jp_elements,
+                arena.alloc([slice_data_ptr, first_element]),
+            )),
+        ));
+
+        let list_data_ptr = root.create_symbol(ident_ids, "list_data_ptr");
+        let list_branch = let_lowlevel(
+            arena,
+            Layout::OPAQUE_PTR,
+            list_data_ptr,
+            PtrCast,
+            &[first_element],
+            arena.alloc(Stmt::Jump(
+                jp_elements,
+                arena.alloc([list_data_ptr, first_element]),
+            )),
+        );
+
+        let switch_slice_list = arena.alloc(first_element_stmt(arena.alloc(
+            //
+            Stmt::if_then_else(
+                root.arena,
+                is_slice,
+                Layout::UNIT,
+                slice_branch,
+                arena.alloc(list_branch),
+            ),
+        )));
+
+        let modify_list = modify_refcount(
+            root,
+            ident_ids,
+            ctx,
+            Pointer::ToData(data_pointer),
+            alignment,
+            ret_stmt,
+        );
+
+        let modify_elems_and_list = refcount_list_elems(
             root,
             ident_ids,
             ctx,
@@ -1055,20 +1626,18 @@ fn refcount_list<'a>(
             len,
             first_element_pointer,
             modify_list,
-        )
-    } else {
-        modify_list
-    };
+        );
 
-    //
-    // JoinPoint for slice vs list
-    //
+        //
+        // JoinPoint for slice vs list
+        //
 
-    let joinpoint_elems = Stmt::Join {
-        id: jp_elements,
-        parameters: arena.alloc([param_data_pointer, param_first_element_pointer]),
-        body: arena.alloc(modify_elems_and_list),
-        remainder: arena.alloc(switch_slice_list),
+        Stmt::Join {
+            id: jp_elements,
+            parameters: arena.alloc([param_data_pointer, param_first_element_pointer]),
+            body: arena.alloc(modify_elems_and_list),
+            remainder: switch_slice_list,
+        }
     };
 
     //
@@ -1081,7 +1650,7 @@ fn refcount_list<'a>(
             //
             is_slice_stmt(arena.alloc(
                 //
-                joinpoint_elems,
+                modify_slice_or_list,
             )),
         )),
     );
@@ -1096,12 +1665,9 @@ fn refcount_list<'a>(
 
     len_stmt(arena.alloc(
         //
-        zero_stmt(arena.alloc(
+        is_empty_stmt(arena.alloc(
             //
-            is_empty_stmt(arena.alloc(
-                //
-                if_empty_stmt,
-            )),
+            if_empty_stmt,
         )),
     ))
 }
@@ -1130,12 +1696,8 @@ fn refcount_list_elems<'a>(
     // Loop initialisation
     //
 
-    // let size = literal int
-    let elem_size = root.create_symbol(ident_ids, "elem_size");
-    let elem_size_expr = Expr::Literal(Literal::Int(
-        (layout_interner.stack_size(elem_layout) as i128).to_ne_bytes(),
-    ));
-    let elem_size_stmt = |next| Stmt::Let(elem_size, elem_size_expr, layout_isize, next);
+    let elem_size = layout_interner.stack_size(elem_layout) as i128;
+    let elem_size = hoisted_literal(root, ident_ids, ctx, "elem_size", elem_size, layout_isize);
 
     // let list_size = len * size
     let list_size = root.create_symbol(ident_ids, "list_size");
@@ -1244,21 +1806,16 @@ fn refcount_list_elems<'a>(
                 arena.alloc(if_end_of_list),
             ),
         ),
-        remainder: root
-            .arena
-            .alloc(Stmt::Jump(elems_loop, arena.alloc([start, end]))),
+        remainder: arena.alloc(Stmt::Jump(elems_loop, arena.alloc([start]))),
     };
 
     start_stmt(arena.alloc(
         //
-        elem_size_stmt(arena.alloc(
+        list_size_stmt(arena.alloc(
             //
-            list_size_stmt(arena.alloc(
+            end_stmt(arena.alloc(
                 //
-                end_stmt(arena.alloc(
-                    //
-                    joinpoint_loop,
-                )),
+                joinpoint_loop,
             )),
         )),
     ))
@@ -1274,31 +1831,37 @@ fn refcount_struct<'a>(
 ) -> Stmt<'a> {
     let mut stmt = rc_return_stmt(root, ident_ids, ctx);
 
-    for (i, field_layout) in field_layouts.iter().enumerate().rev() {
-        if layout_interner.contains_refcounted(*field_layout) {
-            let field_val = root.create_symbol(ident_ids, &format!("field_val_{i}"));
-            let field_val_expr = Expr::StructAtIndex {
-                index: i as u64,
-                field_layouts,
-                structure,
-            };
-            let field_val_stmt = |next| Stmt::Let(field_val, field_val_expr, *field_layout, next);
-
-            let mod_unit = root.create_symbol(ident_ids, &format!("mod_field_{i}"));
-            let mod_args = refcount_args(root, ctx, field_val);
-            let mod_expr = root
-                .call_specialized_op(ident_ids, ctx, layout_interner, *field_layout, mod_args)
-                .unwrap();
-            let mod_stmt = |next| Stmt::Let(mod_unit, mod_expr, LAYOUT_UNIT, next);
+    // Only fields with something to refcount get read. Wide structs are often mostly numbers.
+    let refcounted_fields = field_layouts
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, field_layout)| layout_interner.contains_refcounted(*field_layout))
+        .collect_in::<Vec<_>>(root.arena);
+
+    for (i, field_layout) in refcounted_fields.into_iter().rev() {
+        let field_val = root.create_symbol(ident_ids, &format!("field_val_{i}"));
+        let field_val_expr = Expr::StructAtIndex {
+            index: i as u64,
+            field_layouts,
+            structure,
+        };
+        let field_val_stmt = |next| Stmt::Let(field_val, field_val_expr, field_layout, next);
 
-            stmt = field_val_stmt(root.arena.alloc(
+        let mod_unit = root.create_symbol(ident_ids, &format!("mod_field_{i}"));
+        let mod_args = refcount_args(root, ctx, field_val);
+        let mod_expr = root
+            .call_specialized_op(ident_ids, ctx, layout_interner, field_layout, mod_args)
+            .unwrap();
+        let mod_stmt = |next| Stmt::Let(mod_unit, mod_expr, LAYOUT_UNIT, next);
+
+        stmt = field_val_stmt(root.arena.alloc(
+            //
+            mod_stmt(root.arena.alloc(
                 //
-                mod_stmt(root.arena.alloc(
-                    //
-                    stmt,
-                )),
-            ))
-        }
+                stmt,
+            )),
+        ))
     }
 
     stmt
@@ -1309,7 +1872,6 @@ fn refcount_union<'a>(
     ident_ids: &mut IdentIds,
     ctx: &mut Context<'a>,
     layout_interner: &mut STLayoutInterner<'a>,
-    union_in_layout: InLayout<'a>,
     union: UnionLayout<'a>,
     structure: Symbol,
 ) -> Stmt<'a> {
@@ -1332,7 +1894,7 @@ fn refcount_union<'a>(
         ),
 
         Recursive(tags) => {
-            let tailrec_idx = root.union_tail_recursion_fields(union_in_layout, union);
+            let tailrec_idx = root.union_tail_recursion_fields(layout_interner, union);
             if let (Some(tail_idx), true) = (tailrec_idx, ctx.op.is_dec()) {
                 refcount_union_tailrec(
                     root,
@@ -1359,6 +1921,16 @@ fn refcount_union<'a>(
             }
         }
 
+        NonNullableUnwrapped([boxed_layout]) => refcount_single_field_union(
+            root,
+            ident_ids,
+            ctx,
+            layout_interner,
+            union,
+            *boxed_layout,
+            structure,
+        ),
+
         NonNullableUnwrapped(field_layouts) => {
             // We don't do tail recursion on NonNullableUnwrapped.
             // Its RecursionPointer is always nested inside a List, Option, or other sub-layout, since
@@ -1382,7 +1954,7 @@ fn refcount_union<'a>(
             nullable_id,
         } => {
             let null_id = Some(nullable_id);
-            let tailrec_idx = root.union_tail_recursion_fields(union_in_layout, union);
+            let tailrec_idx = root.union_tail_recursion_fields(layout_interner, union);
             if let (Some(tail_idx), true) = (tailrec_idx, ctx.op.is_dec()) {
                 refcount_union_tailrec(
                     root,
@@ -1415,7 +1987,7 @@ fn refcount_union<'a>(
         } => {
             let null_id = Some(nullable_id as TagIdIntType);
             let tags = root.arena.alloc([other_fields]);
-            let tailrec_idx = root.union_tail_recursion_fields(union_in_layout, union);
+            let tailrec_idx = root.union_tail_recursion_fields(layout_interner, union);
             if let (Some(tail_idx), true) = (tailrec_idx, ctx.op.is_dec()) {
                 refcount_union_tailrec(
                     root,
@@ -1488,6 +2060,7 @@ fn refcount_union_nonrec<'a>(
             structure,
             tag_id_sym,
             tag_id_layout,
+            LAYOUT_UNIT,
             continuation,
         );
 
@@ -1505,27 +2078,38 @@ fn refcount_union_contents<'a>(
     layout_interner: &mut STLayoutInterner<'a>,
     union_layout: UnionLayout<'a>,
     tag_layouts: &'a [&'a [InLayout<'a>]],
-    null_id: Option<TagIdIntType>,
+    null_branch: Option<(TagIdIntType, Stmt<'a>)>,
     structure: Symbol,
     tag_id_sym: Symbol,
     tag_id_layout: InLayout<'a>,
+    ret_layout: InLayout<'a>,
     next_stmt: Stmt<'a>,
 ) -> Stmt<'a> {
     let jp_contents_modified = JoinPointId(root.create_symbol(ident_ids, "jp_contents_modified"));
     let mut tag_branches = Vec::with_capacity_in(tag_layouts.len() + 1, root.arena);
 
-    if let Some(id) = null_id {
-        let ret = rc_return_stmt(root, ident_ids, ctx);
-        tag_branches.push((id as u64, BranchInfo::None, ret));
+    // In debug builds, mark a unique union while we visit its fields, to catch reference cycles
+    let is_recursive = !matches!(union_layout, UnionLayout::NonRecursive(_));
+    let jp_fields_modified = if is_recursive && ctx.op.is_dec() && root.check_refcounts {
+        Some(JoinPointId(
+            root.create_symbol(ident_ids, "jp_fields_modified"),
+        ))
+    } else {
+        None
     };
 
+    // A null pointer has no allocation, so the caller decides where it goes
+    if let Some((id, null_stmt)) = null_branch {
+        tag_branches.push((id as u64, BranchInfo::None, null_stmt));
+    }
+
     for (field_layouts, tag_id) in tag_layouts
         .iter()
-        .zip((0..).filter(|tag_id| !matches!(null_id, Some(id) if tag_id == &id)))
+        .zip((0..).filter(|tag_id| !union_layout.tag_is_null(*tag_id)))
     {
         // After refcounting the fields, jump to modify the union itself
         // (Order is important, to avoid use-after-free for Dec)
-        let following = Stmt::Jump(jp_contents_modified, &[]);
+        let following = Stmt::Jump(jp_fields_modified.unwrap_or(jp_contents_modified), &[]);
 
         let field_layouts = field_layouts
             .iter()
@@ -1556,7 +2140,7 @@ fn refcount_union_contents<'a>(
         cond_layout: tag_id_layout,
         branches: tag_branches.into_bump_slice(),
         default_branch: (BranchInfo::None, root.arena.alloc(default_stmt)),
-        ret_layout: LAYOUT_UNIT,
+        ret_layout,
     };
 
     if let UnionLayout::NonRecursive(_) = union_layout {
@@ -1569,11 +2153,28 @@ fn refcount_union_contents<'a>(
     } else {
         let is_unique = root.create_symbol(ident_ids, "is_unique");
 
+        let rc_contents_stmt = match jp_fields_modified {
+            Some(jp_visited) => mark_while_freeing(
+                root,
+                ident_ids,
+                ctx,
+                layout_interner,
+                union_layout,
+                structure,
+                jp_visited,
+                &[],
+                jp_contents_modified,
+                ret_layout,
+                tag_id_switch,
+            ),
+            None => tag_id_switch,
+        };
+
         let switch_with_unique_check = Stmt::if_then_else(
             root.arena,
             is_unique,
-            Layout::UNIT,
-            tag_id_switch,
+            ret_layout,
+            rc_contents_stmt,
             root.arena.alloc(Stmt::Jump(jp_contents_modified, &[])),
         );
 
@@ -1635,6 +2236,11 @@ fn refcount_union_rec<'a>(
     };
 
     let rc_contents_then_structure = if ctx.op.is_dec() {
+        // If this is null, there is no refcount and no fields. Just return.
+        let null_branch = union_layout.nullable_id().map(|id| {
+            debug_assert!(union_layout.tag_is_null(id));
+            (id, rc_return_stmt(root, ident_ids, ctx))
+        });
         refcount_union_contents(
             root,
             ident_ids,
@@ -1642,23 +2248,127 @@ fn refcount_union_rec<'a>(
             layout_interner,
             union_layout,
             tag_layouts,
-            null_id,
+            null_branch,
             structure,
             tag_id_sym,
             tag_id_layout,
+            LAYOUT_UNIT,
             rc_structure_stmt,
         )
     } else {
         rc_structure_stmt
     };
 
-    if ctx.op.is_decref() && null_id.is_none() {
+    let body = if ctx.op.is_decref() && null_id.is_none() {
         rc_contents_then_structure
     } else {
         tag_id_stmt(root.arena.alloc(
             //
             rc_contents_then_structure,
         ))
+    };
+
+    if ctx.op.is_dec() {
+        check_union_ptr_alignment(root, ident_ids, ctx, union_layout, structure, body)
+    } else {
+        body
+    }
+}
+
+/// Generate a procedure to modify the reference count of a single-field `NonNullableUnwrapped`
+///
+/// This is the layout of `Box a`. It has no tag id to read, so we can go straight to the one
+/// field, and skip it entirely if it has nothing to refcount.
+/// Recursive single-field unions like `Rose : [Rose (List Rose)]` also take this path, so they
+/// skip the tail-recursion loop. Their recursion is always nested inside the field anyway.
+fn refcount_single_field_union<'a>(
+    root: &mut CodeGenHelp<'a>,
+    ident_ids: &mut IdentIds,
+    ctx: &mut Context<'a>,
+    layout_interner: &mut STLayoutInterner<'a>,
+    union_layout: UnionLayout<'a>,
+    boxed_layout: InLayout<'a>,
+    structure: Symbol,
+) -> Stmt<'a> {
+    let arena = root.arena;
+
+    let rc_box_stmt = {
+        let alignment = LayoutRepr::Union(union_layout).allocation_alignment_bytes(layout_interner);
+        let ret_stmt = rc_return_stmt(root, ident_ids, ctx);
+
+        modify_refcount(
+            root,
+            ident_ids,
+            ctx,
+            Pointer::ToData(structure),
+            alignment,
+            arena.alloc(ret_stmt),
+        )
+    };
+
+    if !ctx.op.is_dec() || !layout_interner.contains_refcounted(boxed_layout) {
+        return rc_box_stmt;
+    }
+
+    // If this is the last reference to the box, decrement the boxed value before freeing it
+    let jp_boxed_modified = JoinPointId(root.create_symbol(ident_ids, "jp_boxed_modified"));
+
+    // In debug builds, mark a unique box while we visit its payload, to catch reference cycles
+    let jp_fields_modified = if root.check_refcounts {
+        JoinPointId(root.create_symbol(ident_ids, "jp_fields_modified"))
+    } else {
+        jp_boxed_modified
+    };
+    let rc_boxed_stmt = refcount_tag_fields(
+        root,
+        ident_ids,
+        ctx,
+        layout_interner,
+        union_layout,
+        arena.alloc([(0, boxed_layout)]),
+        structure,
+        0,
+        Stmt::Jump(jp_fields_modified, &[]),
+    );
+    let rc_boxed_stmt = if root.check_refcounts {
+        mark_while_freeing(
+            root,
+            ident_ids,
+            ctx,
+            layout_interner,
+            union_layout,
+            structure,
+            jp_fields_modified,
+            &[],
+            jp_boxed_modified,
+            LAYOUT_UNIT,
+            rc_boxed_stmt,
+        )
+    } else {
+        rc_boxed_stmt
+    };
+
+    let is_unique = root.create_symbol(ident_ids, "is_unique");
+    let if_unique_stmt = Stmt::if_then_else(
+        arena,
+        is_unique,
+        Layout::UNIT,
+        rc_boxed_stmt,
+        arena.alloc(Stmt::Jump(jp_boxed_modified, &[])),
+    );
+
+    Stmt::Join {
+        id: jp_boxed_modified,
+        parameters: &[],
+        body: arena.alloc(rc_box_stmt),
+        remainder: arena.alloc(let_lowlevel(
+            arena,
+            Layout::BOOL,
+            is_unique,
+            LowLevel::RefCountIsUnique,
+            &[structure],
+            arena.alloc(if_unique_stmt),
+        )),
     }
 }
 
@@ -1736,8 +2446,19 @@ fn refcount_union_tailrec<'a>(
         let jp_modify_union = JoinPointId(root.create_symbol(ident_ids, "jp_modify_union"));
         let mut tag_branches = Vec::with_capacity_in(tag_layouts.len() + 1, root.arena);
 
+        // In debug builds, mark a unique node while visiting its other fields, to catch cycles
+        let jp_fields_modified = if root.check_refcounts {
+            Some(JoinPointId(
+                root.create_symbol(ident_ids, "jp_fields_modified"),
+            ))
+        } else {
+            None
+        };
+        let jp_after_fields = jp_fields_modified.unwrap_or(jp_modify_union);
+
         // If this is null, there is no refcount, no `next`, no fields. Just return.
-        if let Some(id) = null_id {
+        if let Some(id) = union_layout.nullable_id() {
+            debug_assert!(union_layout.tag_is_null(id));
             let ret = rc_return_stmt(root, ident_ids, ctx);
             tag_branches.push((id as u64, BranchInfo::None, ret));
         }
@@ -1766,8 +2487,10 @@ fn refcount_union_tailrec<'a>(
                                 structure: current,
                             };
                             let jump_params = root.arena.alloc([field_val]);
-                            let jump = root.arena.alloc(Stmt::Jump(jp_modify_union, jump_params));
-                            tail_stmt = Some(Stmt::Let(field_val, field_val_expr, *field, jump));
+                            let jump = root.arena.alloc(Stmt::Jump(jp_after_fields, jump_params));
+                            // The field is a recursive pointer, but it's passed on as the union
+                            // itself, so bind it with the layout of the join point's param
+                            tail_stmt = Some(Stmt::Let(field_val, field_val_expr, layout, jump));
                         }
                     }
 
@@ -1778,7 +2501,7 @@ fn refcount_union_tailrec<'a>(
 
                     let tail_stmt = null_stmt(root.arena.alloc(
                         //
-                        Stmt::Jump(jp_modify_union, root.arena.alloc([null])),
+                        Stmt::Jump(jp_after_fields, root.arena.alloc([null])),
                     ));
 
                     let field_layouts = field_layouts
@@ -1816,6 +2539,30 @@ fn refcount_union_tailrec<'a>(
             ret_layout: LAYOUT_UNIT,
         };
 
+        let rc_fields_stmt = match jp_fields_modified {
+            Some(jp_visited) => {
+                let visited_next = root.create_symbol(ident_ids, "visited_next");
+                let visited_params = root.arena.alloc([Param {
+                    symbol: visited_next,
+                    layout,
+                }]);
+                mark_while_freeing(
+                    root,
+                    ident_ids,
+                    ctx,
+                    layout_interner,
+                    union_layout,
+                    current,
+                    jp_visited,
+                    visited_params,
+                    jp_modify_union,
+                    LAYOUT_UNIT,
+                    tag_id_switch,
+                )
+            }
+            None => tag_id_switch,
+        };
+
         let is_unique = root.create_symbol(ident_ids, "is_unique");
         let null_pointer = root.create_symbol(ident_ids, "null_pointer");
 
@@ -1833,7 +2580,7 @@ fn refcount_union_tailrec<'a>(
             root.arena,
             is_unique,
             Layout::UNIT,
-            tag_id_switch,
+            rc_fields_stmt,
             root.arena.alloc(jump_with_null_ptr),
         );
 
@@ -1859,10 +2606,17 @@ fn refcount_union_tailrec<'a>(
         }
     };
 
-    let loop_body = tag_id_stmt(root.arena.alloc(
-        //
-        rc_contents_then_structure,
-    ));
+    let loop_body = check_union_ptr_alignment(
+        root,
+        ident_ids,
+        ctx,
+        union_layout,
+        current,
+        tag_id_stmt(root.arena.alloc(
+            //
+            rc_contents_then_structure,
+        )),
+    );
 
     let loop_init = Stmt::Jump(tailrec_loop, root.arena.alloc([initial_structure]));
     let union_layout = layout_interner.insert_direct_no_semantic(LayoutRepr::Union(union_layout));
@@ -1922,3 +2676,659 @@ fn refcount_tag_fields<'a>(
 
     stmt
 }
+
+#[cfg(test)]
+mod test {
+    use roc_collections::MutMap;
+    use roc_module::symbol::{Interns, ModuleId};
+    use roc_target::TargetInfo;
+
+    use super::*;
+    use crate::debug::{check_helper_procs, format_problems};
+    use crate::ir::{Proc, ProcLayout};
+    use crate::layout::{FunctionPointer, Niche};
+
+    const TARGET_INFO: TargetInfo = TargetInfo::default_x86_64();
+
+    fn gen_helpers<'a>(
+        arena: &'a Bump,
+        interner: &mut STLayoutInterner<'a>,
+        layout: InLayout<'a>,
+        op: HelperOp,
+    ) -> Vec<'a, Proc<'a>> {
+        let mut ident_ids = IdentIds::default();
+        let mut root = CodeGenHelp::new(arena, TARGET_INFO, ModuleId::ATTR);
+        root.gen_refcount_proc(&mut ident_ids, interner, layout, op);
+        root.take_procs()
+    }
+
+    /// Run the mono IR checker over every helper generated for this layout
+    fn check_helpers<'a>(
+        arena: &'a Bump,
+        interner: &mut STLayoutInterner<'a>,
+        layout: InLayout<'a>,
+        op: HelperOp,
+        check_refcounts: bool,
+    ) {
+        // Start after the builtin symbols like ARG_1, so that new symbols can't shadow them
+        let mut all_ident_ids = IdentIds::exposed_builtins(0);
+        let mut ident_ids = all_ident_ids.get(&ModuleId::ATTR).unwrap().clone();
+        let mut root = CodeGenHelp::new(arena, TARGET_INFO, ModuleId::ATTR);
+        root.check_refcounts = check_refcounts;
+        root.gen_refcount_proc(&mut ident_ids, interner, layout, op);
+
+        let procs: MutMap<_, _> = root
+            .take_procs()
+            .into_iter()
+            .map(|proc| {
+                let proc_layout = ProcLayout {
+                    arguments: arena.alloc_slice_fill_iter(proc.args.iter().map(|(l, _)| *l)),
+                    result: proc.ret_layout,
+                    niche: Niche::NONE,
+                };
+                ((proc.name.name(), proc_layout), proc)
+            })
+            .collect();
+
+        // A switch that can return from the helper must say what it returns.
+        // (Inlined DecRef code has no returns, only jumps, so it can't know the caller's layout.)
+        for proc in procs.values() {
+            let mut stmts = std::vec::Vec::new();
+            all_stmts(&proc.body, &mut stmts);
+            for stmt in stmts {
+                if let Stmt::Switch { ret_layout, .. } = stmt {
+                    let mut inner = std::vec::Vec::new();
+                    all_stmts(stmt, &mut inner);
+                    if inner.iter().any(|s| matches!(s, Stmt::Ret(_))) {
+                        assert_eq!(*ret_layout, proc.ret_layout, "switch in {:?}", proc.name);
+                    }
+                }
+            }
+        }
+
+        let problems = check_helper_procs(arena, interner, &procs);
+        if !problems.is_empty() {
+            all_ident_ids.insert(ModuleId::ATTR, ident_ids);
+            let interns = Interns {
+                all_ident_ids,
+                ..Default::default()
+            };
+            let formatted = format_problems(&interns, interner, problems);
+            panic!("IR problems found:\n{formatted}");
+        }
+    }
+
+    fn all_stmts<'a, 'b>(stmt: &'b Stmt<'a>, out: &mut std::vec::Vec<&'b Stmt<'a>>) {
+        out.push(stmt);
+        match stmt {
+            Stmt::Let(_, _, _, next) => all_stmts(next, out),
+            Stmt::Switch {
+                branches,
+                default_branch,
+                ..
+            } => {
+                for (_, _, branch) in branches.iter() {
+                    all_stmts(branch, out);
+                }
+                all_stmts(default_branch.1, out);
+            }
+            Stmt::Join {
+                body, remainder, ..
+            } => {
+                all_stmts(body, out);
+                all_stmts(remainder, out);
+            }
+            Stmt::Refcounting(_, next) => all_stmts(next, out),
+            Stmt::Expect { remainder, .. }
+            | Stmt::ExpectFx { remainder, .. }
+            | Stmt::Dbg { remainder, .. } => all_stmts(remainder, out),
+            Stmt::Ret(_) | Stmt::Jump(..) | Stmt::Crash(..) => {}
+        }
+    }
+
+    fn lowlevels(stmt: &Stmt<'_>) -> std::vec::Vec<LowLevel> {
+        let mut stmts = std::vec::Vec::new();
+        all_stmts(stmt, &mut stmts);
+        stmts
+            .into_iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Let(
+                    _,
+                    Expr::Call(Call {
+                        call_type: CallType::LowLevel { op, .. },
+                        ..
+                    }),
+                    _,
+                    _,
+                ) => Some(*op),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The first argument layout of every helper called from this proc
+    fn called_layouts<'a>(proc: &Proc<'a>) -> std::vec::Vec<InLayout<'a>> {
+        let mut stmts = std::vec::Vec::new();
+        all_stmts(&proc.body, &mut stmts);
+        stmts
+            .into_iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Let(
+                    _,
+                    Expr::Call(Call {
+                        call_type: CallType::ByName { arg_layouts, .. },
+                        ..
+                    }),
+                    _,
+                    _,
+                ) => Some(arg_layouts[0]),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn struct_with_unowned_pointers_only_refcounts_owned_fields() {
+        let arena = Bump::new();
+        let mut interner = STLayoutInterner::with_capacity(4, TARGET_INFO);
+
+        // `{ handle: Ptr U8, vtable: { hash: fn }, name: Str }`,
+        // where `handle` is owned by the host and `vtable` holds a function pointer
+        let handle = interner.insert_direct_no_semantic(LayoutRepr::Ptr(Layout::U8));
+        let hash_fn =
+            interner.insert_direct_no_semantic(LayoutRepr::FunctionPointer(FunctionPointer {
+                args: arena.alloc([Layout::STR]),
+                ret: Layout::U64,
+            }));
+        let vtable = interner.insert_direct_no_semantic(LayoutRepr::Struct(arena.alloc([hash_fn])));
+        let fields = arena.alloc([handle, vtable, Layout::STR]);
+        let layout = interner.insert_direct_no_semantic(LayoutRepr::Struct(fields));
+
+        for op in [HelperOp::Inc, HelperOp::Dec] {
+            let procs = gen_helpers(&arena, &mut interner, layout, op);
+            assert_eq!(called_layouts(&procs[0]), [Layout::STR]);
+        }
+    }
+
+    #[test]
+    fn refcounted_field_offsets_table() {
+        use crate::code_gen_help::refcounted_field_offsets;
+
+        let arena = Bump::new();
+        let mut interner = STLayoutInterner::with_capacity(4, TARGET_INFO);
+
+        // `{ Str, I64, List I64 }`
+        let list_i64 =
+            interner.insert_direct_no_semantic(LayoutRepr::Builtin(Builtin::List(Layout::I64)));
+        let fields = arena.alloc([Layout::STR, Layout::I64, list_i64]);
+        let layout = interner.insert_direct_no_semantic(LayoutRepr::Struct(fields));
+
+        // Every field is word-aligned, so there is no padding. The List comes after a Str
+        // (3 words) and an I64 (1 word), which is 32 bytes on our 64-bit target.
+        let str_size = interner.stack_size(Layout::STR);
+        let i64_size = interner.stack_size(Layout::I64);
+        let offsets = refcounted_field_offsets(&arena, &interner, layout).unwrap();
+        assert_eq!(offsets, [0, str_size + i64_size]);
+
+        // A nested record's fields are listed at their offsets in the outer value,
+        // and the outer Str comes after the whole inner record
+        let outer = interner
+            .insert_direct_no_semantic(LayoutRepr::Struct(arena.alloc([layout, Layout::STR])));
+        let inner_size = interner.stack_size(layout);
+        let offsets = refcounted_field_offsets(&arena, &interner, outer).unwrap();
+        assert_eq!(offsets, [0, str_size + i64_size, inner_size]);
+
+        // A Str in a tag payload has no fixed offset
+        let str_tag: &[InLayout] = arena.alloc([Layout::STR]);
+        let i64_tag: &[InLayout] = arena.alloc([Layout::I64]);
+        let union_layout = UnionLayout::NonRecursive(arena.alloc([str_tag, i64_tag]));
+        let union = interner.insert_direct_no_semantic(LayoutRepr::Union(union_layout));
+        assert!(refcounted_field_offsets(&arena, &interner, union).is_none());
+    }
+
+    #[test]
+    fn reset_checks_refcount_alignment_before_load() {
+        let arena = Bump::new();
+        let mut interner = STLayoutInterner::with_capacity(4, TARGET_INFO);
+
+        // `Tree : [Leaf Str, Node Tree Tree]`
+        let rec_ptr =
+            interner.insert_direct_no_semantic(LayoutRepr::RecursivePointer(Layout::VOID));
+        let leaf: &[InLayout] = arena.alloc([Layout::STR]);
+        let node: &[InLayout] = arena.alloc([rec_ptr, rec_ptr]);
+        let tree = interner.insert_recursive(
+            &arena,
+            Layout::no_semantic(
+                LayoutRepr::Union(UnionLayout::Recursive(arena.alloc([leaf, node]))).direct(),
+            ),
+        );
+
+        for check_refcounts in [false, true] {
+            let mut ident_ids = IdentIds::default();
+            let mut root = CodeGenHelp::new(&arena, TARGET_INFO, ModuleId::ATTR);
+            root.check_refcounts = check_refcounts;
+            root.gen_refcount_proc(&mut ident_ids, &mut interner, tree, HelperOp::Reset);
+            let procs = root.take_procs();
+
+            let body = &procs[0].body;
+            let ops = lowlevels(body);
+            let load = ops.iter().position(|op| *op == PtrLoad);
+
+            // The first `NumBitwiseAnd` clears the tag id bits, and the second checks alignment
+            let ands: std::vec::Vec<_> = (0..ops.len())
+                .filter(|i| ops[*i] == NumBitwiseAnd)
+                .collect();
+
+            let mut stmts = std::vec::Vec::new();
+            all_stmts(body, &mut stmts);
+            let crashes = stmts.iter().any(|s| matches!(s, Stmt::Crash(..)));
+
+            if check_refcounts {
+                assert!(ands[1] < load.unwrap());
+                assert!(crashes);
+            } else {
+                assert_eq!(ands.len(), 1);
+                assert!(!crashes);
+            }
+        }
+    }
+
+    /// A rose tree, `Tree : [Node Str (List Tree)]`
+    fn rose_tree<'a>(arena: &'a Bump, interner: &mut STLayoutInterner<'a>) -> UnionLayout<'a> {
+        let naked_rec_ptr =
+            interner.insert_direct_no_semantic(LayoutRepr::RecursivePointer(Layout::VOID));
+        let children =
+            interner.insert_direct_no_semantic(LayoutRepr::Builtin(Builtin::List(naked_rec_ptr)));
+        let tree = interner.insert_recursive(
+            arena,
+            Layout::no_semantic(
+                LayoutRepr::Union(UnionLayout::NonNullableUnwrapped(
+                    arena.alloc([Layout::STR, children]),
+                ))
+                .direct(),
+            ),
+        );
+        match interner.get_repr(tree) {
+            LayoutRepr::Union(union_layout) => union_layout,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn consecutive_incs_are_folded() {
+        let arena = Bump::new();
+        let mut interner = STLayoutInterner::with_capacity(4, TARGET_INFO);
+        let mut ident_ids = IdentIds::default();
+        let mut root = CodeGenHelp::new(&arena, TARGET_INFO, ModuleId::ATTR);
+
+        // inc ARG_1 2; inc ARG_1 3; inc ARG_2 1; ret ARG_1
+        let ret = arena.alloc(Stmt::Ret(Symbol::ARG_1));
+        let inc_other = arena.alloc(Stmt::Refcounting(ModifyRc::Inc(Symbol::ARG_2, 1), ret));
+        let inc_again = arena.alloc(Stmt::Refcounting(
+            ModifyRc::Inc(Symbol::ARG_1, 3),
+            inc_other,
+        ));
+        let (stmt, _) = root.expand_refcount_stmt(
+            &mut ident_ids,
+            &mut interner,
+            Layout::STR,
+            &ModifyRc::Inc(Symbol::ARG_1, 2),
+            inc_again,
+        );
+
+        // One call with the total, then the increment of the other symbol is left alone
+        let mut stmts = std::vec::Vec::new();
+        all_stmts(stmt, &mut stmts);
+        let calls = stmts
+            .iter()
+            .filter(|s| matches!(s, Stmt::Let(_, Expr::Call(_), _, _)))
+            .count();
+        assert_eq!(calls, 1);
+        assert!(stmts.iter().any(|s| matches!(
+            s,
+            Stmt::Let(_, Expr::Literal(Literal::Int(bytes)), _, _) if *bytes == 5i128.to_ne_bytes()
+        )));
+        assert!(stmts
+            .iter()
+            .any(|s| matches!(s, Stmt::Refcounting(ModifyRc::Inc(Symbol::ARG_2, 1), _))));
+    }
+
+    #[test]
+    fn is_unique_checks_the_allocation() {
+        let arena = Bump::new();
+        let mut interner = STLayoutInterner::with_capacity(4, TARGET_INFO);
+
+        // `ConsList : [Nil, Cons Str ConsList]`
+        let rec_ptr =
+            interner.insert_direct_no_semantic(LayoutRepr::RecursivePointer(Layout::VOID));
+        let cons_list = interner.insert_recursive(
+            &arena,
+            Layout::no_semantic(
+                LayoutRepr::Union(UnionLayout::NullableUnwrapped {
+                    nullable_id: false,
+                    other_fields: arena.alloc([Layout::STR, rec_ptr]),
+                })
+                .direct(),
+            ),
+        );
+        let list_str =
+            interner.insert_direct_no_semantic(LayoutRepr::Builtin(Builtin::List(Layout::STR)));
+
+        let record =
+            interner.insert_direct_no_semantic(LayoutRepr::Struct(arena.alloc([list_str])));
+
+        // Strings and lists check either their own allocation or the one they are a slice of.
+        // Values stored inline have no allocation of their own to check.
+        for (layout, checks) in [(Layout::STR, 2), (list_str, 2), (cons_list, 1), (record, 0)] {
+            let procs = gen_helpers(&arena, &mut interner, layout, HelperOp::IsUnique);
+            assert_eq!(procs.len(), 1);
+            assert_eq!(procs[0].ret_layout, Layout::BOOL);
+
+            let ops = lowlevels(&procs[0].body);
+            let is_unique_checks = ops.iter().filter(|op| **op == RefCountIsUnique).count();
+            assert_eq!(is_unique_checks, checks);
+        }
+    }
+
+    #[test]
+    fn dec_checks_union_pointer_alignment_before_reading_it() {
+        let arena = Bump::new();
+        let mut interner = STLayoutInterner::with_capacity(4, TARGET_INFO);
+
+        // `Tree : [Leaf Str, Node Tree Tree]`, with the tag id stored in the pointer
+        let rec_ptr =
+            interner.insert_direct_no_semantic(LayoutRepr::RecursivePointer(Layout::VOID));
+        let leaf: &[InLayout] = arena.alloc([Layout::STR]);
+        let node: &[InLayout] = arena.alloc([rec_ptr, rec_ptr]);
+        let tree = interner.insert_recursive(
+            &arena,
+            Layout::no_semantic(
+                LayoutRepr::Union(UnionLayout::Recursive(arena.alloc([leaf, node]))).direct(),
+            ),
+        );
+
+        for check_refcounts in [false, true] {
+            let mut ident_ids = IdentIds::default();
+            let mut root = CodeGenHelp::new(&arena, TARGET_INFO, ModuleId::ATTR);
+            root.check_refcounts = check_refcounts;
+            root.gen_refcount_proc(&mut ident_ids, &mut interner, tree, HelperOp::Dec);
+            let procs = root.take_procs();
+
+            let mut stmts = std::vec::Vec::new();
+            all_stmts(&procs[0].body, &mut stmts);
+            let crashes = stmts.iter().any(|s| matches!(s, Stmt::Crash(..)));
+            let first_tag_read = stmts
+                .iter()
+                .position(|s| matches!(s, Stmt::Let(_, Expr::GetTagId { .. }, _, _)))
+                .unwrap();
+            let checked_before_read = stmts[..first_tag_read].iter().any(|s| {
+                matches!(
+                    s,
+                    Stmt::Let(
+                        _,
+                        Expr::Call(Call {
+                            call_type: CallType::LowLevel {
+                                op: NumBitwiseAnd,
+                                ..
+                            },
+                            ..
+                        }),
+                        _,
+                        _,
+                    )
+                )
+            });
+
+            assert_eq!(checked_before_read, check_refcounts);
+            assert_eq!(crashes, check_refcounts);
+        }
+    }
+
+    #[test]
+    fn dec_checks_for_underflow_only_when_enabled() {
+        let arena = Bump::new();
+        let mut interner = STLayoutInterner::with_capacity(4, TARGET_INFO);
+        let list_str =
+            interner.insert_direct_no_semantic(LayoutRepr::Builtin(Builtin::List(Layout::STR)));
+
+        for layout in [Layout::STR, list_str] {
+            for check_refcounts in [false, true] {
+                let mut ident_ids = IdentIds::default();
+                let mut root = CodeGenHelp::new(&arena, TARGET_INFO, ModuleId::ATTR);
+                root.check_refcounts = check_refcounts;
+                root.gen_refcount_proc(&mut ident_ids, &mut interner, layout, HelperOp::Dec);
+                let procs = root.take_procs();
+                let proc = procs.iter().find(|p| p.args[0].0 == layout).unwrap();
+
+                // Every decrement is preceded by a check that the refcount is still live
+                let ops = lowlevels(&proc.body);
+                let decs = ops.iter().filter(|op| **op == RefCountDecDataPtr).count();
+                let checks = ops.iter().filter(|op| **op == NumLte).count();
+                let loads = ops.iter().filter(|op| **op == PtrLoad).count();
+                if check_refcounts {
+                    assert_eq!(checks, decs);
+                    assert!(loads >= decs);
+                } else {
+                    assert_eq!(checks, 0);
+                }
+
+                // Each check tells a reference cycle apart from a double free
+                let mut stmts = std::vec::Vec::new();
+                all_stmts(&proc.body, &mut stmts);
+                let crashes = |prefix: &str| {
+                    stmts
+                        .iter()
+                        .filter(|s| {
+                            matches!(s, Stmt::Let(_, Expr::Literal(Literal::Str(msg)), _, _)
+                                if msg.starts_with(prefix))
+                        })
+                        .count()
+                };
+                assert_eq!(crashes("Reference cycle"), checks);
+                assert_eq!(crashes("Refcount underflow"), checks);
+            }
+        }
+    }
+
+    #[test]
+    fn dec_marks_unions_while_freeing_their_children_only_when_enabled() {
+        let arena = Bump::new();
+        let mut interner = STLayoutInterner::with_capacity(4, TARGET_INFO);
+        let union_layout = rose_tree(&arena, &mut interner);
+        let tree = interner.insert_direct_no_semantic(LayoutRepr::Union(union_layout));
+
+        // `ConsList : [Nil, Cons Str ConsList]`, whose spine is freed in a loop
+        let rec_ptr =
+            interner.insert_direct_no_semantic(LayoutRepr::RecursivePointer(Layout::VOID));
+        let cons_list = interner.insert_recursive(
+            &arena,
+            Layout::no_semantic(
+                LayoutRepr::Union(UnionLayout::NullableUnwrapped {
+                    nullable_id: true,
+                    other_fields: arena.alloc([Layout::STR, rec_ptr]),
+                })
+                .direct(),
+            ),
+        );
+
+        for layout in [tree, cons_list] {
+            for check_refcounts in [false, true] {
+                let mut ident_ids = IdentIds::default();
+                let mut root = CodeGenHelp::new(&arena, TARGET_INFO, ModuleId::ATTR);
+                root.check_refcounts = check_refcounts;
+                root.gen_refcount_proc(&mut ident_ids, &mut interner, layout, HelperOp::Dec);
+                let procs = root.take_procs();
+                let proc = procs.iter().find(|p| p.args[0].0 == layout).unwrap();
+
+                // The refcount is marked before the children are visited, and restored after
+                let stores = lowlevels(&proc.body)
+                    .into_iter()
+                    .filter(|op| *op == PtrStore)
+                    .count();
+                assert_eq!(stores, if check_refcounts { 2 } else { 0 });
+            }
+        }
+    }
+
+    #[test]
+    fn generated_helpers_pass_the_ir_checker() {
+        let arena = Bump::new();
+        let mut interner = STLayoutInterner::with_capacity(4, TARGET_INFO);
+
+        let list_str =
+            interner.insert_direct_no_semantic(LayoutRepr::Builtin(Builtin::List(Layout::STR)));
+        let list_i64 =
+            interner.insert_direct_no_semantic(LayoutRepr::Builtin(Builtin::List(Layout::I64)));
+        let record = interner
+            .insert_direct_no_semantic(LayoutRepr::Struct(arena.alloc([Layout::STR, list_i64])));
+        let str_fields: &[InLayout] = arena.alloc([Layout::STR]);
+        let list_fields: &[InLayout] = arena.alloc([list_str]);
+        let result = interner.insert_direct_no_semantic(LayoutRepr::Union(
+            UnionLayout::NonRecursive(arena.alloc([str_fields, list_fields])),
+        ));
+        let rose_tree = rose_tree(&arena, &mut interner);
+        let rose_tree = interner.insert_direct_no_semantic(LayoutRepr::Union(rose_tree));
+        let rec_ptr =
+            interner.insert_direct_no_semantic(LayoutRepr::RecursivePointer(Layout::VOID));
+        let cons_list = interner.insert_recursive(
+            &arena,
+            Layout::no_semantic(
+                LayoutRepr::Union(UnionLayout::NullableUnwrapped {
+                    nullable_id: true,
+                    other_fields: arena.alloc([Layout::STR, rec_ptr]),
+                })
+                .direct(),
+            ),
+        );
+
+        for layout in [Layout::STR, list_str, record, result, rose_tree, cons_list] {
+            for op in [HelperOp::Inc, HelperOp::Dec, HelperOp::IsUnique] {
+                for check_refcounts in [false, true] {
+                    check_helpers(&arena, &mut interner, layout, op, check_refcounts);
+                }
+            }
+        }
+
+        for layout in [rose_tree, cons_list] {
+            for op in [HelperOp::Reset, HelperOp::ResetRef] {
+                for check_refcounts in [false, true] {
+                    check_helpers(&arena, &mut interner, layout, op, check_refcounts);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn refcount_math_uses_the_target_pointer_size() {
+        let arena = Bump::new();
+        let target_info = TargetInfo::default_wasm32();
+        let mut interner = STLayoutInterner::with_capacity(4, target_info);
+
+        // `Tree : [Leaf Str, Node Tree Tree]`, which stores its tag id in the pointer
+        let rec_ptr =
+            interner.insert_direct_no_semantic(LayoutRepr::RecursivePointer(Layout::VOID));
+        let leaf: &[InLayout] = arena.alloc([Layout::STR]);
+        let node: &[InLayout] = arena.alloc([rec_ptr, rec_ptr]);
+        let tree = interner.insert_recursive(
+            &arena,
+            Layout::no_semantic(
+                LayoutRepr::Union(UnionLayout::Recursive(arena.alloc([leaf, node]))).direct(),
+            ),
+        );
+
+        // Reset finds the refcount in the IR. Dec only does so when checking refcounts.
+        let mut ident_ids = IdentIds::default();
+        let mut root = CodeGenHelp::new(&arena, target_info, ModuleId::ATTR);
+        root.check_refcounts = true;
+        root.gen_refcount_proc(&mut ident_ids, &mut interner, tree, HelperOp::Dec);
+        root.gen_refcount_proc(&mut ident_ids, &mut interner, tree, HelperOp::Reset);
+
+        let mut literals = MutMap::default();
+        let mut rc_offsets = std::vec::Vec::new();
+        for proc in root.take_procs() {
+            let mut stmts = std::vec::Vec::new();
+            all_stmts(&proc.body, &mut stmts);
+            for stmt in stmts {
+                match stmt {
+                    Stmt::Let(sym, Expr::Literal(Literal::Int(bytes)), layout, _) => {
+                        literals.insert(*sym, (i128::from_ne_bytes(*bytes), *layout));
+                    }
+                    Stmt::Let(_, Expr::Call(call), _, _) => {
+                        if let CallType::LowLevel {
+                            op: NumSubSaturated,
+                            ..
+                        } = call.call_type
+                        {
+                            rc_offsets.push(call.arguments[1]);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // The tag id is in the low 2 bits of a 4-byte aligned pointer
+        assert!(literals.values().any(|lit| *lit == (-4, Layout::I32)));
+
+        // The refcount is one 4-byte word before the data
+        assert!(!rc_offsets.is_empty());
+        for offset in rc_offsets {
+            assert_eq!(literals[&offset], (4, Layout::I32));
+        }
+
+        // No math is done at 64 bits
+        assert!(literals
+            .values()
+            .all(|(_, layout)| *layout != Layout::I64 && *layout != Layout::U64));
+    }
+
+    #[test]
+    fn literals_hoisted_in_recursive_union_dec() {
+        let arena = Bump::new();
+        let mut interner = STLayoutInterner::with_capacity(4, TARGET_INFO);
+
+        // A cons list of strings: `ConsList : [Nil, Cons Str ConsList]`
+        let rec_ptr =
+            interner.insert_direct_no_semantic(LayoutRepr::RecursivePointer(Layout::VOID));
+        let union_layout = UnionLayout::NullableUnwrapped {
+            nullable_id: false,
+            other_fields: arena.alloc([Layout::STR, rec_ptr]),
+        };
+        let layout = interner.insert_recursive(
+            &arena,
+            Layout::no_semantic(LayoutRepr::Union(union_layout).direct()),
+        );
+
+        let procs = gen_helpers(&arena, &mut interner, layout, HelperOp::Dec);
+
+        for proc in procs.iter() {
+            let mut stmts = std::vec::Vec::new();
+            all_stmts(&proc.body, &mut stmts);
+            let literals: std::vec::Vec<_> = stmts
+                .iter()
+                .filter_map(|stmt| match stmt {
+                    Stmt::Let(_, Expr::Literal(Literal::Int(bytes)), layout, _) => {
+                        Some((*bytes, *layout))
+                    }
+                    _ => None,
+                })
+                .collect();
+
+            // Each constant is bound once, before anything else in the helper
+            let mut leading = 0;
+            let mut stmt = &proc.body;
+            while let Stmt::Let(_, Expr::Literal(_), _, next) = stmt {
+                leading += 1;
+                stmt = next;
+            }
+            assert_eq!(leading, literals.len(), "{:?}", proc.name);
+
+            let distinct: std::collections::HashSet<_> = literals.iter().collect();
+            assert_eq!(distinct.len(), literals.len());
+
+            // ...including the alignment, even though the Str helper decrements in two places
+            let alignments = literals.iter().filter(|(_, l)| *l == LAYOUT_U32).count();
+            assert_eq!(alignments, 1);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 mod checker;
 mod report;
 
-pub use checker::{check_procs, Problem, Problems};
+pub use checker::{check_helper_procs, check_procs, Problem, Problems};
 pub use report::format_problems;
@@ -161,6 +161,25 @@ pub fn check_procs<'a>(
     arena: &'a Bump,
     interner: &mut STLayoutInterner<'a>,
     procs: &Procs<'a>,
+) -> Problems<'a> {
+    check_procs_help(arena, interner, procs, true)
+}
+
+/// Check the helper procs generated in the backends by `CodeGenHelp`.
+/// These are created after specialization, so all of their calls share `CallSpecId::BACKEND_DUMMY`.
+pub fn check_helper_procs<'a>(
+    arena: &'a Bump,
+    interner: &mut STLayoutInterner<'a>,
+    procs: &Procs<'a>,
+) -> Problems<'a> {
+    check_procs_help(arena, interner, procs, false)
+}
+
+fn check_procs_help<'a>(
+    arena: &'a Bump,
+    interner: &mut STLayoutInterner<'a>,
+    procs: &Procs<'a>,
+    check_call_spec_ids: bool,
 ) -> Problems<'a> {
     let mut problems = Default::default();
 
@@ -173,6 +192,7 @@ pub fn check_procs<'a>(
             ret_layout: proc.ret_layout,
             problems: &mut problems,
             call_spec_ids: Default::default(),
+            check_call_spec_ids,
             procs,
             venv: Default::default(),
             joinpoints: Default::default(),
@@ -195,6 +215,7 @@ struct Ctx<'a, 'r> {
     proc_layout: ProcLayout<'a>,
     procs: &'r Procs<'a>,
     call_spec_ids: CallSpecIds,
+    check_call_spec_ids: bool,
     ret_layout: InLayout<'a>,
     venv: VEnv<'a>,
     joinpoints: JoinPoints<'a>,
@@ -437,9 +458,9 @@ impl<'a, 'r> Ctx<'a, 'r> {
             &Expr::StructAtIndex {
                 index,
                 // TODO: pass the field order hash down, so we can check this
-                field_layouts: _,
+                field_layouts,
                 structure,
-            } => self.check_struct_at_index(structure, index),
+            } => self.check_struct_at_index(structure, index, field_layouts),
             Expr::GetTagId {
                 structure: _,
                 union_layout,
@@ -548,10 +569,21 @@ impl<'a, 'r> Ctx<'a, 'r> {
         }
     }
 
-    fn check_struct_at_index(&mut self, structure: Symbol, index: u64) -> Option<InLayout<'a>> {
+    fn check_struct_at_index(
+        &mut self,
+        structure: Symbol,
+        index: u64,
+        field_layouts: &'a [InLayout<'a>],
+    ) -> Option<InLayout<'a>> {
         self.with_sym_layout(structure, |ctx, def_line, layout| {
             let layout = ctx.resolve(layout);
             match ctx.interner.get_repr(layout) {
+                // Str and List are three words. Refcounting helpers read those words directly.
+                LayoutRepr::Builtin(Builtin::Str | Builtin::List(_))
+                    if field_layouts.len() == 3 && index < 3 =>
+                {
+                    Some(field_layouts[index as usize])
+                }
                 LayoutRepr::Struct(field_layouts) => {
                     if index as usize >= field_layouts.len() {
                         ctx.problem(ProblemKind::StructIndexOOB {
@@ -704,10 +736,12 @@ impl<'a, 'r> Ctx<'a, 'r> {
                 for (arg, wanted_layout) in arguments.iter().zip(arg_layouts.iter()) {
                     self.check_sym_layout(*arg, *wanted_layout, UseKind::CallArg);
                 }
-                if let Some(old_call_line) =
-                    self.call_spec_ids.insert(*specialization_id, self.line)
-                {
-                    self.problem(ProblemKind::DuplicateCallSpecId { old_call_line });
+                if self.check_call_spec_ids {
+                    if let Some(old_call_line) =
+                        self.call_spec_ids.insert(*specialization_id, self.line)
+                    {
+                        self.problem(ProblemKind::DuplicateCallSpecId { old_call_line });
+                    }
                 }
                 Some(*ret_layout)
             }
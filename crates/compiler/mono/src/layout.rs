@@ -1028,6 +1028,19 @@ impl<'a> UnionLayout<'a> {
         }
     }
 
+    /// The id of the nullable tag, if there is one. Values with that tag are null pointers.
+    pub fn nullable_id(&self) -> Option<TagIdIntType> {
+        match self {
+            UnionLayout::NonRecursive(_)
+            | UnionLayout::Recursive(_)
+            | UnionLayout::NonNullableUnwrapped(_) => None,
+            UnionLayout::NullableWrapped { nullable_id, .. } => Some(*nullable_id),
+            UnionLayout::NullableUnwrapped { nullable_id, .. } => {
+                Some(*nullable_id as TagIdIntType)
+            }
+        }
+    }
+
     fn tags_alignment_bytes<I>(interner: &I, tags: &[&'a [InLayout<'a>]]) -> u32
     where
         I: LayoutInterner<'a>,
@@ -14,6 +14,12 @@ fn main() {
         build_wasm_test_host();
         build_wasm_linking_test_host();
     }
+
+    if feature_is_enabled("gen-dev") && cfg!(target_os = "linux") {
+        // The dev backend's refcount tests load the app as a dylib that calls back into
+        // roc_alloc and roc_dealloc in the test binary, so those must be exported
+        println!("cargo:rustc-link-arg-tests=-rdynamic");
+    }
 }
 
 const fn object_file_extension() -> &'static str {
@@ -1,6 +1,9 @@
 #[cfg(feature = "gen-wasm")]
 use crate::helpers::{wasm::assert_refcounts, RefCount::*};
 
+#[cfg(feature = "gen-dev")]
+use crate::helpers::{dev::assert_refcounts, RefCount::*};
+
 #[allow(unused_imports)]
 use indoc::indoc;
 
@@ -47,6 +50,27 @@ fn str_dealloc() {
     );
 }
 
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn str_small_and_big_dealloc() {
+    // Small strings live on the stack, so only the big string has a refcount to drop
+    assert_refcounts!(
+        indoc!(
+            r#"
+                s = Str.concat "A long enough string " "to be heap-allocated"
+                small = Str.concat "short" "er"
+
+                List.len [s, small, s]
+            "#
+        ),
+        usize,
+        &[
+            Deallocated, // s
+            Deallocated  // [s, small, s]
+        ]
+    );
+}
+
 #[test]
 #[cfg(feature = "gen-wasm")]
 fn list_int_inc() {
@@ -83,6 +107,91 @@ fn list_int_dealloc() {
     );
 }
 
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn list_int_slice_inc() {
+    // The slice shares its parent's allocation, and its refcount
+    assert_refcounts!(
+        indoc!(
+            r#"
+                list = [0x111, 0x222, 0x333]
+                slice = List.dropFirst list 1
+                [slice, slice, slice]
+            "#
+        ),
+        RocList<RocList<i64>>,
+        &[
+            Live(3), // list
+            Live(1)  // result
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn list_int_slice_dealloc() {
+    assert_refcounts!(
+        indoc!(
+            r#"
+                list = [0x111, 0x222, 0x333]
+                slice = List.dropFirst list 1
+                List.len [slice, slice, slice]
+            "#
+        ),
+        usize,
+        &[
+            Deallocated, // list
+            Deallocated  // result
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn list_of_empty_records_dealloc() {
+    // The elements take no space, so there is nothing to step through
+    assert_refcounts!(
+        indoc!(
+            r#"
+                s = Str.concat "A long enough string " "to be heap-allocated"
+
+                record = { units: [{}, {}, {}], s }
+
+                List.len record.units
+            "#
+        ),
+        usize,
+        &[
+            Deallocated, // s
+            Deallocated, // units
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn list_str_drop_element_in_memory() {
+    // The builtin drops the element where it lies in the list, through a pointer to it
+    assert_refcounts!(
+        indoc!(
+            r#"
+                a = Str.concat "A long enough string " "to be heap-allocated"
+                b = Str.concat "Another long enough string " "to be heap-allocated"
+                c = Str.concat "A third long enough string " "to be heap-allocated"
+
+                List.dropAt [a, b, c] 1
+            "#
+        ),
+        RocList<RocStr>,
+        &[
+            Live(1),     // a
+            Deallocated, // b
+            Live(1),     // c
+            Live(1),     // list
+        ]
+    );
+}
+
 #[test]
 #[cfg(feature = "gen-wasm")]
 fn list_str_inc() {
@@ -123,6 +232,56 @@ fn list_str_dealloc() {
     );
 }
 
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn list_of_results_dealloc() {
+    // Each element is a Str or an I64 followed by the tag id. The Dec loop must step over all of it.
+    assert_refcounts!(
+        indoc!(
+            r#"
+                a = Str.concat "A long enough string " "to be heap-allocated"
+                b = Str.concat "Another long enough string " "to be heap-allocated"
+
+                results : List (Result Str I64)
+                results = [Ok a, Err 1, Ok b]
+
+                List.len results
+            "#
+        ),
+        usize,
+        &[
+            Deallocated, // a
+            Deallocated, // b
+            Deallocated, // results
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn list_of_closures_dealloc() {
+    // The closures capture different values, so each element drops the captures of its own closure
+    assert_refcounts!(
+        indoc!(
+            r#"
+                s = Str.concat "A long enough string " "to be heap-allocated"
+                l = [1, 2, 3]
+
+                countBytes = \{} -> Str.countUtf8Bytes s
+                countNums = \{} -> List.len l
+
+                List.len [countBytes, countNums]
+            "#
+        ),
+        usize,
+        &[
+            Deallocated, // s
+            Deallocated, // l
+            Deallocated, // [countBytes, countNums]
+        ]
+    );
+}
+
 #[test]
 #[cfg(feature = "gen-wasm")]
 fn struct_inc() {
@@ -158,6 +317,89 @@ fn struct_dealloc() {
     );
 }
 
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn struct_with_nil_union_field_dealloc() {
+    // `next` is a null pointer, which the record's Dec helper must not read through
+    assert_refcounts!(
+        indoc!(
+            r#"
+                LinkedList : [Nil, Cons Str LinkedList]
+
+                s = Str.concat "A long enough string " "to be heap-allocated"
+
+                record : { head : Str, next : LinkedList }
+                record = { head: s, next: Nil }
+
+                when record.next is
+                    Nil -> 0i64
+                    Cons _ _ -> 1
+            "#
+        ),
+        i64,
+        &[Deallocated] // s
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn struct_with_list_of_numbers_dealloc() {
+    assert_refcounts!(
+        indoc!(
+            r#"
+                record = { n: 1i64, l: [1i64, 2, 3] }
+
+                record.n
+            "#
+        ),
+        i64,
+        &[Deallocated] // l
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn struct_with_reordered_fields_dealloc() {
+    // `{ a: U8, b: Str, c: U8 }` is laid out as `{ b: Str, a: U8, c: U8 }`
+    assert_refcounts!(
+        indoc!(
+            r#"
+                s = Str.concat "A long enough string " "to be heap-allocated"
+
+                record = { a: 1u8, b: s, c: 2u8 }
+
+                if record.a == record.c then 0i64 else 1i64
+            "#
+        ),
+        i64,
+        &[Deallocated] // s
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn wide_struct_dealloc() {
+    // Only one of the 20 fields is refcounted
+    assert_refcounts!(
+        indoc!(
+            r#"
+                s = Str.concat "A long enough string " "to be heap-allocated"
+
+                record = {
+                    n0: 0i64, n1: 1i64, n2: 2i64, n3: 3i64, n4: 4i64,
+                    n5: 5i64, n6: 6i64, n7: 7i64, n8: 8i64, n9: 9i64,
+                    n10: 10i64, n11: 11i64, n12: 12i64, n13: 13i64, n14: 14i64,
+                    n15: 15i64, n16: 16i64, n17: 17i64, n18: 18i64, s,
+                }
+
+                record.n18
+            "#
+        ),
+        i64,
+        &[Deallocated] // s
+    );
+}
+
 #[test]
 #[cfg(feature = "gen-wasm")]
 fn union_nonrecursive_inc() {
@@ -207,6 +449,117 @@ fn union_nonrecursive_dec() {
     );
 }
 
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn union_large_inline_payload_dec() {
+    // The record is stored inline in the union, and each of its fields is dropped
+    assert_refcounts!(
+        indoc!(
+            r#"
+                Payload : [A { s : Str, l : List I64 }, B I64]
+
+                s = Str.concat "A long enough string " "to be heap-allocated"
+                l = [1, 2, 3]
+
+                payload : Payload
+                payload = A { s, l }
+
+                when payload is
+                    A _ -> 1i64
+                    B x -> x
+            "#
+        ),
+        i64,
+        &[
+            Deallocated, // s
+            Deallocated, // l
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn union_wide_nonrecursive_dec() {
+    // Every tag's arm of the Dec helper continues to the same return
+    assert_refcounts!(
+        indoc!(
+            r#"
+                Wide : [A Str, B Str, C Str, D Str, E Str, F Str]
+
+                s = Str.concat "A long enough string " "to be heap-allocated"
+
+                wide : Wide
+                wide = E s
+
+                when wide is
+                    A _ -> 1i64
+                    B _ -> 2
+                    C _ -> 3
+                    D _ -> 4
+                    E _ -> 5
+                    F _ -> 6
+            "#
+        ),
+        i64,
+        &[Deallocated] // s
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn union_of_unrelated_payloads_dec() {
+    assert_refcounts!(
+        indoc!(
+            r#"
+                U : [A Str, B (List I64), C { x: F64 }]
+
+                s = Str.concat "A long enough string " "to be heap-allocated"
+
+                values : List U
+                values = [A s, B [1, 2, 3], C { x: 1.5 }]
+
+                List.len values
+            "#
+        ),
+        usize,
+        &[
+            Deallocated, // s
+            Deallocated, // [1, 2, 3]
+            Deallocated, // values
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn union_of_lists_dec() {
+    // Each tag drops its own kind of list. Only the `List Str` has elements to drop.
+    assert_refcounts!(
+        indoc!(
+            r#"
+                Lists : [Ints (List I64), Strs (List Str)]
+
+                s = Str.concat "A long enough string " "to be heap-allocated"
+
+                ints : Lists
+                ints = Ints [1, 2, 3]
+
+                strs : Lists
+                strs = Strs [s, s]
+
+                List.len [ints, strs]
+            "#
+        ),
+        usize,
+        &[
+            Deallocated, // s
+            Deallocated, // [1, 2, 3]
+            Deallocated, // [s, s]
+            Deallocated, // [ints, strs]
+        ]
+    );
+}
+
 #[test]
 #[cfg(feature = "gen-wasm")]
 fn union_recursive_inc() {
@@ -265,6 +618,64 @@ fn union_recursive_dec() {
     );
 }
 
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn union_recursive_over_aligned_dec() {
+    // The payload is aligned to 16 bytes, but the refcount is still the word before the data
+    assert_refcounts!(
+        indoc!(
+            r#"
+                Tree : [Leaf I128, Node Tree Tree]
+
+                x : Tree
+                x = Leaf 1
+
+                e : Tree
+                e = Node x x
+
+                when e is
+                    Node y _ -> y
+                    Leaf _ -> e
+            "#
+        ),
+        Pointer,
+        &[
+            Live(1),     // x
+            Deallocated  // e
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn list_of_recursive_unions_dec() {
+    // Each element is a pointer to a tree, dropped by the tree's own helper
+    assert_refcounts!(
+        indoc!(
+            r#"
+                Tree : [Leaf Str, Node Tree Tree]
+
+                s = Str.concat "A long enough string " "to be heap-allocated"
+
+                leaf : Tree
+                leaf = Leaf s
+
+                node : Tree
+                node = Node leaf leaf
+
+                List.len [node, leaf]
+            "#
+        ),
+        usize,
+        &[
+            Deallocated, // s
+            Deallocated, // leaf
+            Deallocated, // node
+            Deallocated, // [node, leaf]
+        ]
+    );
+}
+
 #[test]
 #[cfg(feature = "gen-wasm")]
 fn refcount_different_rosetrees_inc() {
@@ -346,6 +757,148 @@ fn refcount_different_rosetrees_dec() {
     );
 }
 
+#[test]
+#[cfg(any(feature = "gen-wasm", feature = "gen-dev"))]
+fn union_with_list_of_itself_dec() {
+    // The `Node` list's elements are `RecursivePointer`s back to `Tree`
+    assert_refcounts!(
+        indoc!(
+            r#"
+                Tree : [Leaf Str, Node (List Tree)]
+
+                s = Str.concat "A long enough string " "to be heap-allocated"
+
+                leaf : Tree
+                leaf = Leaf s
+
+                inner : Tree
+                inner = Node [leaf, leaf]
+
+                tree : Tree
+                tree = Node [leaf, leaf, leaf, inner]
+
+                when tree is
+                    Leaf _ -> 0i64
+                    Node _ -> 1i64
+        "#
+        ),
+        i64,
+        &[
+            Deallocated, // s
+            Deallocated, // leaf
+            Deallocated, // [leaf, leaf]
+            Deallocated, // inner
+            Deallocated, // [leaf, leaf, leaf, inner]
+            Deallocated, // tree
+        ]
+    );
+}
+
+#[test]
+#[cfg(any(feature = "gen-wasm", feature = "gen-dev"))]
+fn union_non_nullable_cons_list_dec() {
+    // A `NonNullableUnwrapped` cons list. Its tail is a `RecursivePointer` inside a list.
+    assert_refcounts!(
+        indoc!(
+            r#"
+                ConsList : [Cons Str (List ConsList)]
+
+                s = Str.concat "A long enough string " "to be heap-allocated"
+
+                last : ConsList
+                last = Cons s []
+
+                middle : ConsList
+                middle = Cons s [last]
+
+                first : ConsList
+                first = Cons s [middle]
+
+                when first is
+                    Cons x _ -> x
+        "#
+        ),
+        RocStr,
+        &[
+            Live(1),     // s
+            Deallocated, // last
+            Deallocated, // [last]
+            Deallocated, // middle
+            Deallocated, // [middle]
+            Deallocated, // first
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn union_with_number_prefixes_dec() {
+    // Each tag has an `I64` in front of its heap-allocated field
+    assert_refcounts!(
+        indoc!(
+            r#"
+                Tree : [Leaf I64 Str, Node I64 (List Tree)]
+
+                s = Str.concat "A long enough string " "to be heap-allocated"
+
+                leaf : Tree
+                leaf = Leaf 1 s
+
+                tree : Tree
+                tree = Node 2 [leaf, leaf]
+
+                when tree is
+                    Leaf n _ -> n
+                    Node n _ -> n
+        "#
+        ),
+        i64,
+        &[
+            Deallocated, // s
+            Deallocated, // leaf
+            Deallocated, // [leaf, leaf]
+            Deallocated, // tree
+        ]
+    );
+}
+
+#[test]
+#[cfg(any(feature = "gen-wasm", feature = "gen-dev"))]
+fn union_with_broad_list_of_itself_dec() {
+    assert_refcounts!(
+        indoc!(
+            r#"
+                Tree : [Leaf Str, Node (List Tree)]
+
+                s = Str.concat "A long enough string " "to be heap-allocated"
+
+                leaf : Tree
+                leaf = Leaf s
+
+                leaves : List Tree
+                leaves = List.repeat leaf 100
+
+                tree : Tree
+                tree = Node [Node leaves, Node leaves, leaf]
+
+                when tree is
+                    Leaf _ -> 0i64
+                    Node _ -> 1i64
+        "#
+        ),
+        i64,
+        &[
+            Deallocated, // s
+            Deallocated, // leaf
+            Deallocated, // leaves
+            Deallocated, // Node leaves
+            Deallocated, // Node leaves
+            Deallocated, // [Node leaves, Node leaves, leaf]
+            Deallocated, // tree
+        ]
+    );
+}
+
 #[test]
 #[cfg(feature = "gen-wasm")]
 fn union_linked_list_inc() {
@@ -454,6 +1007,105 @@ fn union_linked_list_long_dec() {
     );
 }
 
+#[test]
+#[cfg(any(feature = "gen-wasm", feature = "gen-dev"))]
+fn union_linked_list_of_str_long_dec() {
+    // The spine is freed in a loop, and each element on the way
+    assert_refcounts!(
+        indoc!(
+            r#"
+                app "test" provides [main] to "./platform"
+
+                LinkedList a : [Nil, Cons a (LinkedList a)]
+
+                prependAll = \n, s, tail ->
+                    if n == 0 then
+                        tail
+                    else
+                        prependAll (n-1) s (Cons s tail)
+
+                main =
+                    s = Str.concat "A long enough string " "to be heap-allocated"
+
+                    linked : LinkedList Str
+                    linked = prependAll 1_000 s Nil
+
+                    when linked is
+                        Cons _ _ -> 1i64
+                        Nil -> 0
+                "#
+        ),
+        i64,
+        &[Deallocated; 1_001]
+    );
+}
+
+#[test]
+#[cfg(any(feature = "gen-wasm", feature = "gen-dev"))]
+fn union_binary_tree_dec() {
+    // Only one child of each node can be the loop's next pointer
+    assert_refcounts!(
+        indoc!(
+            r#"
+                app "test" provides [main] to "./platform"
+
+                Tree : [Leaf I64, Node Tree Tree]
+
+                build : I64 -> Tree
+                build = \depth ->
+                    if depth == 0 then
+                        Leaf depth
+                    else
+                        Node (build (depth - 1)) (build (depth - 1))
+
+                main =
+                    tree = build 6
+
+                    when tree is
+                        Leaf n -> n
+                        Node _ _ -> 1
+                "#
+        ),
+        i64,
+        &[Deallocated; 127]
+    );
+}
+
+#[test]
+#[cfg(any(feature = "gen-wasm", feature = "gen-dev"))]
+fn union_nullable_wrapped_list_dec() {
+    assert_refcounts!(
+        indoc!(
+            r#"
+                app "test" provides [main] to "./platform"
+
+                List2 : [Nil, One I64 List2, Two Str List2]
+
+                prependAll = \n, s, tail ->
+                    if n == 0 then
+                        tail
+                    else if n % 2 == 0 then
+                        prependAll (n-1) s (One n tail)
+                    else
+                        prependAll (n-1) s (Two s tail)
+
+                main =
+                    s = Str.concat "A long enough string " "to be heap-allocated"
+
+                    list : List2
+                    list = prependAll 100 s Nil
+
+                    when list is
+                        Nil -> 0i64
+                        One n _ -> n
+                        Two _ _ -> 1
+                "#
+        ),
+        i64,
+        &[Deallocated; 101]
+    );
+}
+
 #[test]
 #[cfg(feature = "gen-wasm")]
 fn boxed_str_inc() {
@@ -571,3 +1223,140 @@ fn reset_reuse_alignment_8() {
         ]
     );
 }
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn list_str_slice_dealloc() {
+    // Dropping from the front makes a seamless slice, which must free its parent allocation
+    assert_refcounts!(
+        indoc!(
+            r#"
+                s = Str.concat "A long enough string " "to be heap-allocated"
+                list = [s, s, s]
+                slice = List.dropFirst list 1
+
+                List.len slice
+            "#
+        ),
+        usize,
+        &[
+            Deallocated, // s
+            Deallocated, // list
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn str_slice_dealloc() {
+    // Trimming leading spaces makes a seamless slice, which must free its parent allocation
+    assert_refcounts!(
+        indoc!(
+            r#"
+                s = Str.concat "    A long enough string " "to be heap-allocated"
+
+                Str.isEmpty (Str.trim s)
+            "#
+        ),
+        bool,
+        &[Deallocated]
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn str_slice_inc() {
+    // Copies of a seamless slice share the refcount of its parent allocation
+    assert_refcounts!(
+        indoc!(
+            r#"
+                s = Str.concat "    A long enough string " "to be heap-allocated"
+                t = Str.trim s
+
+                [t, t]
+            "#
+        ),
+        RocList<RocStr>,
+        &[
+            Live(2), // s
+            Live(1), // result
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-wasm")]
+fn union_linked_list_reset_reuse() {
+    // Each `Cons` cell of the input is reused in place for the output
+    assert_refcounts!(
+        indoc!(
+            r#"
+                app "test" provides [main] to "./platform"
+
+                LinkedList a : [Nil, Cons a (LinkedList a)]
+
+                double : LinkedList I64 -> LinkedList I64
+                double = \list ->
+                    when list is
+                        Nil -> Nil
+                        Cons x rest -> Cons (2 * x) (double rest)
+
+                main =
+                    linked : LinkedList I64
+                    linked = Cons 1 (Cons 2 (Cons 3 Nil))
+
+                    when double linked is
+                        Cons x _ -> x
+                        Nil -> -1
+            "#
+        ),
+        i64,
+        &[
+            Deallocated, // Cons 3 Nil
+            Deallocated, // Cons 2 _
+            Deallocated, // Cons 1 _
+        ]
+    );
+}
+
+/// Drop a string whose refcount the host overwrote with `encoded_refcount` while it was live.
+/// The checks that report what went wrong are only generated with ROC_CHECK_REFCOUNT_HELPERS.
+#[cfg(feature = "gen-dev")]
+fn dec_str_with_overwritten_refcount(encoded_refcount: isize) {
+    use crate::helpers::platform_functions::overwrite_refcount_on_alloc;
+
+    std::env::set_var(roc_debug_flags::ROC_CHECK_REFCOUNT_HELPERS, "1");
+
+    // Overwrite the refcount of `s` when `t` is allocated, before `s` is decremented
+    overwrite_refcount_on_alloc(1, 0, encoded_refcount);
+    assert_refcounts!(
+        indoc!(
+            r#"
+                s = Str.concat "A long enough string " "to be heap-allocated"
+                t = Str.concat "Another long enough string " "to be heap-allocated"
+
+                Str.countUtf8Bytes s + Str.countUtf8Bytes t
+            "#
+        ),
+        u64,
+        &[
+            Deallocated, // s
+            Deallocated, // t
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "gen-dev")]
+#[should_panic(expected = "Reference cycle")]
+fn dec_while_freeing_reports_a_cycle() {
+    // The marker that `mark_while_freeing` stores while an allocation's fields are freed
+    dec_str_with_overwritten_refcount(1);
+}
+
+#[test]
+#[cfg(feature = "gen-dev")]
+#[should_panic(expected = "Refcount underflow")]
+fn dec_after_free_reports_underflow() {
+    dec_str_with_overwritten_refcount(2);
+}
@@ -1,3 +1,4 @@
+use super::RefCount;
 use libloading::Library;
 use roc_build::link::{link, LinkType};
 use roc_builtins::bitcode;
@@ -37,6 +38,7 @@ pub fn helper(
     src: &str,
     _leak: bool,
     lazy_literals: bool,
+    mode: roc_gen_dev::AssemblyBackendMode,
 ) -> (String, Vec<roc_problem::can::Problem>, Library) {
     use std::path::PathBuf;
 
@@ -195,7 +197,7 @@ pub fn helper(
         module_id,
         exposed_to_host: exposed_to_host.top_level_values.keys().copied().collect(),
         lazy_literals,
-        mode: roc_gen_dev::AssemblyBackendMode::Test,
+        mode,
     };
 
     let target = target_lexicon::Triple::host();
@@ -344,8 +346,13 @@ pub(crate) fn asm_evals_to<T, U, F>(
     use bumpalo::Bump;
 
     let arena = Bump::new();
-    let (_main_fn_name, errors, lib) =
-        crate::helpers::dev::helper(&arena, src, leak, lazy_literals);
+    let (_main_fn_name, errors, lib) = crate::helpers::dev::helper(
+        &arena,
+        src,
+        leak,
+        lazy_literals,
+        roc_gen_dev::AssemblyBackendMode::Test,
+    );
 
     let result = crate::helpers::dev::run_test_main::<T>(&lib);
 
@@ -378,6 +385,49 @@ pub(crate) fn identity<T>(x: T) -> T {
     x
 }
 
+#[allow(dead_code)]
+pub(crate) fn assert_dev_refcounts_help<T>(src: &str) -> Vec<RefCount> {
+    use crate::helpers::platform_functions::{init_refcount_test, take_refcount_pointers};
+
+    let arena = bumpalo::Bump::new();
+    let (_main_fn_name, errors, lib) = crate::helpers::dev::helper(
+        &arena,
+        src,
+        true,
+        false,
+        roc_gen_dev::AssemblyBackendMode::RefcountTest,
+    );
+    assert_eq!(errors, std::vec::Vec::new(), "Encountered errors");
+
+    init_refcount_test();
+    let result = crate::helpers::dev::run_test_main::<T>(&lib);
+    let rc_pointers = take_refcount_pointers();
+
+    match result {
+        // The refcounts are checked after `main` returns, so don't let the result decrement them
+        Ok(value) => std::mem::forget(value),
+        Err((msg, _)) => panic!(r#"Roc failed with message: "{msg}""#),
+    }
+
+    rc_pointers
+        .into_iter()
+        .map(|rc_ptr| {
+            if rc_ptr.is_null() {
+                RefCount::Deallocated
+            } else {
+                // Dereference the RC pointer and decode its value from the negative number format
+                let rc_encoded = unsafe { *rc_ptr };
+                if rc_encoded == 0 {
+                    RefCount::Constant
+                } else {
+                    let rc = rc_encoded.wrapping_sub(isize::MIN) + 1;
+                    RefCount::Live(rc as u32)
+                }
+            }
+        })
+        .collect()
+}
+
 #[allow(unused_macros)]
 macro_rules! assert_evals_to {
     ($src:expr, $expected:expr, $ty:ty) => {{
@@ -409,5 +459,16 @@ macro_rules! assert_evals_to {
     };
 }
 
+#[allow(unused_macros)]
+macro_rules! assert_refcounts {
+    ($src: expr, $ty: ty, $expected_refcounts: expr) => {{
+        let actual_refcounts = $crate::helpers::dev::assert_dev_refcounts_help::<$ty>($src);
+        assert_eq!(&actual_refcounts, $expected_refcounts)
+    }};
+}
+
 #[allow(unused_imports)]
 pub(crate) use assert_evals_to;
+
+#[allow(unused_imports)]
+pub(crate) use assert_refcounts;
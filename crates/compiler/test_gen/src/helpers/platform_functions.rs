@@ -1,27 +1,113 @@
 use core::ffi::c_void;
+use std::cell::{Cell, RefCell};
+
+thread_local! {
+    /// The refcount pointers of every allocation made since `init_refcount_test`,
+    /// in allocation order. Freed allocations are replaced by a null pointer.
+    static RC_POINTERS: RefCell<Option<Vec<*const isize>>> = RefCell::new(None);
+
+    /// See `overwrite_refcount_on_alloc`
+    static OVERWRITE_REFCOUNT: Cell<Option<OverwriteRefcount>> = Cell::new(None);
+}
+
+#[derive(Clone, Copy)]
+struct OverwriteRefcount {
+    on_alloc: usize,
+    target: usize,
+    encoded_refcount: isize,
+}
+
+/// When allocation number `on_alloc` is made, overwrite the refcount of allocation number
+/// `target` with `encoded_refcount`, like a buggy host would. Both count from 0 since
+/// `init_refcount_test`. This lets a test corrupt a live value before Roc decrements it.
+#[allow(dead_code)]
+pub fn overwrite_refcount_on_alloc(on_alloc: usize, target: usize, encoded_refcount: isize) {
+    OVERWRITE_REFCOUNT.with(|overwrite| {
+        overwrite.set(Some(OverwriteRefcount {
+            on_alloc,
+            target,
+            encoded_refcount,
+        }))
+    });
+}
+
+/// Start recording the refcount pointers of Roc allocations on this thread
+#[allow(dead_code)]
+pub fn init_refcount_test() {
+    RC_POINTERS.with(|rc_pointers| *rc_pointers.borrow_mut() = Some(Vec::new()));
+}
+
+/// Stop recording, and return the refcount pointers recorded since `init_refcount_test`
+#[allow(dead_code)]
+pub fn take_refcount_pointers() -> Vec<*const isize> {
+    OVERWRITE_REFCOUNT.with(|overwrite| overwrite.set(None));
+    RC_POINTERS.with(|rc_pointers| rc_pointers.borrow_mut().take().unwrap_or_default())
+}
+
+fn alloc_ptr_to_rc_ptr(ptr: *mut c_void, alignment: u32) -> *const isize {
+    let rc_addr = ptr as usize + alignment as usize - core::mem::size_of::<usize>();
+    rc_addr as *const isize
+}
 
 /// # Safety
 /// The Roc application needs this.
 #[no_mangle]
-pub unsafe fn roc_alloc(size: usize, _alignment: u32) -> *mut c_void {
-    libc::malloc(size)
+pub unsafe extern "C" fn roc_alloc(size: usize, alignment: u32) -> *mut c_void {
+    let allocated = libc::malloc(size);
+
+    RC_POINTERS.with(|rc_pointers| {
+        if let Some(rc_pointers) = rc_pointers.borrow_mut().as_mut() {
+            rc_pointers.push(alloc_ptr_to_rc_ptr(allocated, alignment));
+
+            if let Some(overwrite) = OVERWRITE_REFCOUNT.with(|overwrite| overwrite.get()) {
+                if overwrite.on_alloc == rc_pointers.len() - 1 {
+                    let rc_ptr = rc_pointers[overwrite.target] as *mut isize;
+                    *rc_ptr = overwrite.encoded_refcount;
+                }
+            }
+        }
+    });
+
+    allocated
 }
 
 /// # Safety
 /// The Roc application needs this.
 #[no_mangle]
-pub unsafe fn roc_realloc(
+pub unsafe extern "C" fn roc_realloc(
     c_ptr: *mut c_void,
     new_size: usize,
     _old_size: usize,
-    _alignment: u32,
+    alignment: u32,
 ) -> *mut c_void {
-    libc::realloc(c_ptr, new_size)
+    let reallocated = libc::realloc(c_ptr, new_size);
+
+    RC_POINTERS.with(|rc_pointers| {
+        if let Some(rc_pointers) = rc_pointers.borrow_mut().as_mut() {
+            let old_rc_ptr = alloc_ptr_to_rc_ptr(c_ptr, alignment);
+            if let Some(entry) = rc_pointers.iter_mut().find(|rc_ptr| **rc_ptr == old_rc_ptr) {
+                *entry = alloc_ptr_to_rc_ptr(reallocated, alignment);
+            }
+        }
+    });
+
+    reallocated
 }
 
 /// # Safety
 /// The Roc application needs this.
 #[no_mangle]
-pub unsafe fn roc_dealloc(c_ptr: *mut c_void, _alignment: u32) {
+pub unsafe extern "C" fn roc_dealloc(c_ptr: *mut c_void, alignment: u32) {
+    RC_POINTERS.with(|rc_pointers| {
+        if let Some(rc_pointers) = rc_pointers.borrow_mut().as_mut() {
+            // Null out the entry rather than removing it, so the other entries keep their
+            // positions even if malloc reuses the space
+            let rc_ptr = alloc_ptr_to_rc_ptr(c_ptr, alignment);
+            if let Some(entry) = rc_pointers.iter_mut().find(|entry| **entry == rc_ptr) {
+                *entry = core::ptr::null();
+            }
+        }
+    });
+
     libc::free(c_ptr)
 }